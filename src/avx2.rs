@@ -0,0 +1,330 @@
+// AVX2 versions of the NTT, the inverse NTT, the pointwise and sparse multiply-accumulate and caddq
+// they compute the same butterflies in the same order as the scalar code in poly, including montgomery_reduce
+// on the 64 bits products, so their outputs are identical and not merely congruent mod Q
+// poly calls them when the CPU has AVX2 and falls back to the scalar code otherwise, as keccak does for
+// the 4-way Keccak-f[1600]
+use crate::keccak::{keccak_round, StateX4, RC};
+use crate::params::{Q, QINV};
+use crate::poly::{Poly, SparseTernary, ZETAS};
+use core::arch::x86_64::*;
+
+const F: i32 = 41978; // mont^2 /256
+
+cpufeatures::new!(cpuid_avx2, "avx2");
+
+// cpuid is only queried on the first call
+pub(crate) fn available() -> bool {
+    cpuid_avx2::get()
+}
+
+// montgomery_reduce(a * b) in every lane
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn mont_mul(a: __m256i, b: __m256i) -> __m256i {
+    let qinv = _mm256_set1_epi32(QINV);
+    let q = _mm256_set1_epi32(Q);
+    // the 64 bits products of the even and of the odd lanes
+    let prod_even = _mm256_mul_epi32(a, b);
+    let prod_odd = _mm256_mul_epi32(_mm256_srli_epi64(a, 32), _mm256_srli_epi64(b, 32));
+    // m = low 32 bits of the product * QINV, so that the product - m * Q has 32 zero low bits
+    let t_even = _mm256_sub_epi64(prod_even, _mm256_mul_epi32(_mm256_mullo_epi32(prod_even, qinv), q));
+    let t_odd = _mm256_sub_epi64(prod_odd, _mm256_mul_epi32(_mm256_mullo_epi32(prod_odd, qinv), q));
+    // the high 32 bits of every product
+    _mm256_blend_epi32(_mm256_srli_epi64(t_even, 32), t_odd, 0b10101010)
+}
+
+// in the layers with len < 8 the butterflies are within 16 coefficients x and y, which split gathers
+// into a vector a of the first and a vector b of the second elements of the pairs
+// the lanes of a and b then hold the pairs of these blocks of 2 * len coefficients
+const BLOCKS: [[usize; 8]; 3] = [
+    [0, 1, 4, 5, 2, 3, 6, 7], // len 1
+    [0, 0, 2, 2, 1, 1, 3, 3], // len 2
+    [0, 0, 0, 0, 1, 1, 1, 1], // len 4
+];
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn split(x: __m256i, y: __m256i, len: usize) -> (__m256i, __m256i) {
+    match len {
+        1 => {
+            let x = _mm256_shuffle_epi32(x, 0b11_01_10_00);
+            let y = _mm256_shuffle_epi32(y, 0b11_01_10_00);
+            (_mm256_unpacklo_epi64(x, y), _mm256_unpackhi_epi64(x, y))
+        }
+        2 => (_mm256_unpacklo_epi64(x, y), _mm256_unpackhi_epi64(x, y)),
+        _ => (_mm256_permute2x128_si256(x, y, 0x20), _mm256_permute2x128_si256(x, y, 0x31)),
+    }
+}
+
+// the inverse of split
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn join(a: __m256i, b: __m256i, len: usize) -> (__m256i, __m256i) {
+    match len {
+        1 => {
+            let x = _mm256_unpacklo_epi64(a, b);
+            let y = _mm256_unpackhi_epi64(a, b);
+            (_mm256_shuffle_epi32(x, 0b11_01_10_00), _mm256_shuffle_epi32(y, 0b11_01_10_00))
+        }
+        2 => (_mm256_unpacklo_epi64(a, b), _mm256_unpackhi_epi64(a, b)),
+        _ => (_mm256_permute2x128_si256(a, b, 0x20), _mm256_permute2x128_si256(a, b, 0x31)),
+    }
+}
+
+// the zeta of every lane of a layer with len < 8, zeta(i) is the zeta of the i-th block of the 16 coefficients
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn zetas(len: usize, zeta: impl Fn(usize) -> i32) -> __m256i {
+    let blocks = &BLOCKS[len.trailing_zeros() as usize];
+    let z: [i32; 8] = core::array::from_fn(|i| zeta(blocks[i]));
+    _mm256_loadu_si256(z.as_ptr() as *const __m256i)
+}
+
+// forward butterfly, a + zeta * b and a - zeta * b
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn ct_butterfly(a: __m256i, b: __m256i, zeta: __m256i) -> (__m256i, __m256i) {
+    let t = mont_mul(zeta, b);
+    (_mm256_add_epi32(a, t), _mm256_sub_epi32(a, t))
+}
+
+// inverse butterfly, a + b and zeta * (a - b)
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn gs_butterfly(a: __m256i, b: __m256i, zeta: __m256i) -> (__m256i, __m256i) {
+    (_mm256_add_epi32(a, b), mont_mul(_mm256_sub_epi32(a, b), zeta))
+}
+
+// Poly::ntt_in_place
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn ntt(c: &mut [i32; 256]) {
+    let p = c.as_mut_ptr() as *mut __m256i;
+    let mut k: usize = 0;
+    let mut len: usize = 128;
+    while len >= 8 {
+        for start in (0..256).step_by(2 * len) {
+            let zeta = _mm256_set1_epi32(ZETAS[k]);
+            k += 1;
+            for i in (start..start + len).step_by(8) {
+                let a = _mm256_loadu_si256(p.add(i / 8));
+                let b = _mm256_loadu_si256(p.add((i + len) / 8));
+                let (a, b) = ct_butterfly(a, b, zeta);
+                _mm256_storeu_si256(p.add(i / 8), a);
+                _mm256_storeu_si256(p.add((i + len) / 8), b);
+            }
+        }
+        len >>= 1;
+    }
+    while len >= 1 {
+        for j in 0..16 {
+            let (a, b) = split(_mm256_loadu_si256(p.add(2 * j)), _mm256_loadu_si256(p.add(2 * j + 1)), len);
+            let (a, b) = ct_butterfly(a, b, zetas(len, |i| ZETAS[k + i]));
+            let (x, y) = join(a, b, len);
+            _mm256_storeu_si256(p.add(2 * j), x);
+            _mm256_storeu_si256(p.add(2 * j + 1), y);
+            k += 8 / len;
+        }
+        len >>= 1;
+    }
+}
+
+// Poly::intt_in_place
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn intt(c: &mut [i32; 256]) {
+    let p = c.as_mut_ptr() as *mut __m256i;
+    let mut k: usize = 255;
+    let mut len: usize = 1;
+    while len < 8 {
+        for j in 0..16 {
+            let (a, b) = split(_mm256_loadu_si256(p.add(2 * j)), _mm256_loadu_si256(p.add(2 * j + 1)), len);
+            let (a, b) = gs_butterfly(a, b, zetas(len, |i| -ZETAS[k - 1 - i]));
+            let (x, y) = join(a, b, len);
+            _mm256_storeu_si256(p.add(2 * j), x);
+            _mm256_storeu_si256(p.add(2 * j + 1), y);
+            k -= 8 / len;
+        }
+        len <<= 1;
+    }
+    while len < 256 {
+        for start in (0..256).step_by(2 * len) {
+            k -= 1;
+            let zeta = _mm256_set1_epi32(-ZETAS[k]);
+            for i in (start..start + len).step_by(8) {
+                let a = _mm256_loadu_si256(p.add(i / 8));
+                let b = _mm256_loadu_si256(p.add((i + len) / 8));
+                let (a, b) = gs_butterfly(a, b, zeta);
+                _mm256_storeu_si256(p.add(i / 8), a);
+                _mm256_storeu_si256(p.add((i + len) / 8), b);
+            }
+        }
+        len <<= 1;
+    }
+    let f = _mm256_set1_epi32(F);
+    for i in 0..32 {
+        _mm256_storeu_si256(p.add(i), mont_mul(_mm256_loadu_si256(p.add(i)), f));
+    }
+}
+
+// Poly::point_wise_mul_acc, acc += mont(a * b) without reduction of the sum
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn point_wise_mul_acc(acc: &mut [i32; 256], a: &[i32; 256], b: &[i32; 256]) {
+    let (pa, pb) = (a.as_ptr() as *const __m256i, b.as_ptr() as *const __m256i);
+    let pacc = acc.as_mut_ptr() as *mut __m256i;
+    for i in 0..32 {
+        let prod = mont_mul(_mm256_loadu_si256(pa.add(i)), _mm256_loadu_si256(pb.add(i)));
+        _mm256_storeu_si256(pacc.add(i), _mm256_add_epi32(_mm256_loadu_si256(pacc.add(i)), prod));
+    }
+}
+
+// Poly::sparse_mul_acc, the scalar loops vectorized with AVX2
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn sparse_mul_acc(acc: &mut Poly, c: &SparseTernary, a: &Poly) {
+    acc.sparse_mul_acc_scalar(c, a)
+}
+
+// Poly::caddq
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn caddq(c: &mut [i32; 256]) {
+    let p = c.as_mut_ptr() as *mut __m256i;
+    let q = _mm256_set1_epi32(Q);
+    for i in 0..32 {
+        let x = _mm256_loadu_si256(p.add(i));
+        _mm256_storeu_si256(p.add(i), _mm256_add_epi32(x, _mm256_and_si256(_mm256_srai_epi32(x, 31), q)));
+    }
+}
+
+// keccak::keccak_f1600_x4, the i-th lane of the four states is a[i]
+#[target_feature(enable = "avx2")]
+pub(crate) unsafe fn keccak_f1600_x4(state: &mut StateX4) {
+    // a 64 bits rotation of every lane, AVX2 has none
+    macro_rules! rotl {
+        ($x:expr, $n:literal) => {
+            _mm256_or_si256(_mm256_slli_epi64::<$n>($x), _mm256_srli_epi64::<{ 64 - $n }>($x))
+        };
+    }
+    let p = state.as_mut_ptr() as *mut __m256i;
+    let mut a: [__m256i; 25] = core::array::from_fn(|i| _mm256_loadu_si256(p.add(i)));
+    for rc in RC {
+        keccak_round!(a, _mm256_set1_epi64x(rc as i64), _mm256_xor_si256, _mm256_andnot_si256, rotl);
+    }
+    for i in 0..25 {
+        _mm256_storeu_si256(p.add(i), a[i]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keccak::keccak_f1600_x4_scalar;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn random_poly(rng: &mut StdRng, bound: i32) -> Poly {
+        let mut p = Poly::new();
+        p.coeffs.iter_mut().for_each(|c| *c = rng.gen_range(-bound + 1..bound));
+        p
+    }
+
+    // the inputs are random coefficients in (-Q, Q) and the extremes of that range
+    fn inputs() -> impl Iterator<Item = Poly> {
+        let mut rng = StdRng::seed_from_u64(21);
+        let extremes = [Q - 1, -(Q - 1), 0].map(|c| Poly { coeffs: [c; 256] });
+        let alternating = Poly { coeffs: core::array::from_fn(|i| if i % 2 == 0 { Q - 1 } else { -(Q - 1) }) };
+        extremes.into_iter().chain([alternating]).chain((0..500).map(move |_| random_poly(&mut rng, Q)))
+    }
+
+    #[test]
+    fn test_ntt_matches_scalar() {
+        if !available() {
+            return;
+        }
+        for a in inputs() {
+            let mut c = a.coeffs;
+            unsafe { ntt(&mut c) };
+            let mut s = a.copy();
+            s.ntt_scalar();
+            assert_eq!(c, s.coeffs);
+        }
+    }
+
+    #[test]
+    fn test_intt_matches_scalar() {
+        if !available() {
+            return;
+        }
+        for a in inputs() {
+            let mut c = a.coeffs;
+            unsafe { intt(&mut c) };
+            let mut s = a.copy();
+            s.intt_scalar();
+            assert_eq!(c, s.coeffs);
+        }
+    }
+
+    #[test]
+    fn test_point_wise_mul_acc_matches_scalar() {
+        if !available() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(22);
+        for a in inputs() {
+            let b = random_poly(&mut rng, Q);
+            // an accumulator that already holds 6 products
+            let acc = random_poly(&mut rng, 6 * Q);
+            let mut c = acc.coeffs;
+            unsafe { point_wise_mul_acc(&mut c, &a.coeffs, &b.coeffs) };
+            let mut s = acc.copy();
+            s.point_wise_mul_acc_scalar(&a, &b);
+            assert_eq!(c, s.coeffs);
+        }
+    }
+
+    #[test]
+    fn test_sparse_mul_acc_matches_scalar() {
+        if !available() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(25);
+        for a in inputs().take(100) {
+            let mut c = Poly::new();
+            for _ in 0..60 {
+                c.coeffs[rng.gen_range(0..256)] = if rng.gen() { 1 } else { -1 };
+            }
+            let c = SparseTernary::new(&c);
+            // a in (-Q, Q) adds up to less than 2^31 over 64 positions
+            let mut acc = random_poly(&mut rng, 1 << 20);
+            let mut s = acc.copy();
+            unsafe { sparse_mul_acc(&mut acc, &c, &a) };
+            s.sparse_mul_acc_scalar(&c, &a);
+            assert_eq!(acc.coeffs, s.coeffs);
+        }
+    }
+
+    #[test]
+    fn test_caddq_matches_scalar() {
+        if !available() {
+            return;
+        }
+        for a in inputs() {
+            let mut c = a.coeffs;
+            unsafe { caddq(&mut c) };
+            let mut s = a.copy();
+            s.caddq_scalar();
+            assert_eq!(c, s.coeffs);
+        }
+    }
+
+    #[test]
+    fn test_keccak_f1600_x4_matches_scalar() {
+        if !available() {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(24);
+        for _ in 0..100 {
+            let mut a: StateX4 = core::array::from_fn(|_| core::array::from_fn(|_| rng.gen()));
+            let mut s = a;
+            unsafe { keccak_f1600_x4(&mut a) };
+            keccak_f1600_x4_scalar(&mut s);
+            assert_eq!(a, s);
+        }
+    }
+}
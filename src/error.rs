@@ -0,0 +1,83 @@
+use core::fmt;
+
+// errors returned by the typed api and the plain functions of sign and mldsa,
+// only verify returns a bool instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // the byte length does not match any supported parameter set
+    InvalidLength(usize),
+    // the bytes have the right length but are not a valid encoding
+    MalformedEncoding,
+    // only security level 2, 3 and 5 are supported
+    UnsupportedLevel(u8),
+    // the context string is longer than 255 bytes
+    ContextTooLong(usize),
+    // the pre-hash digest does not have the output length of the hash function
+    InvalidDigestLength(usize),
+    // a secret key has s1 or s2 coefficients outside of [-eta, eta]
+    SecretOutOfRange,
+    // tr in a secret key is not the hash of the public key computed from it
+    TrMismatch,
+    // t0 in a secret key is not the low bits of t = A*s1 + s2
+    T0Mismatch,
+    // the parts of a key do not belong together, e.g. a seed and an expanded key from different seeds
+    InconsistentKey,
+    // the signature does not verify under the given key, message and context
+    InvalidSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLength(len) => write!(f, "no parameter set has an encoding of {} bytes", len),
+            Error::MalformedEncoding => write!(f, "malformed encoding"),
+            Error::UnsupportedLevel(level) => write!(f, "security level {} is not supported", level),
+            Error::ContextTooLong(len) => write!(f, "context of {} bytes is longer than 255 bytes", len),
+            Error::InvalidDigestLength(len) => write!(f, "digest of {} bytes has the wrong length", len),
+            Error::SecretOutOfRange => write!(f, "s1 or s2 has coefficients outside of [-eta, eta]"),
+            Error::TrMismatch => write!(f, "tr does not match the public key of the secret key"),
+            Error::T0Mismatch => write!(f, "t0 does not match t = A*s1 + s2 of the secret key"),
+            Error::InconsistentKey => write!(f, "inconsistent key"),
+            Error::InvalidSignature => write!(f, "invalid signature"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+// why verify_detailed rejected a signature, for debugging interop failures
+// the plain verify functions only report accept or reject
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    // the lengths of the public key and the signature do not belong to the same parameter set
+    InvalidLength { pk: usize, sig: usize },
+    // the context string is longer than 255 bytes
+    ContextTooLong(usize),
+    // the signature is not a strict encoding, e.g. hint indices out of order or non-zero padding
+    MalformedEncoding,
+    // the infinity norm of z is at least gamma1 - beta
+    ZNormTooLarge,
+    // the signature has more than omega hints
+    TooManyHints,
+    // the challenge recomputed from w1 differs from c tilde in the signature
+    ChallengeMismatch,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidLength { pk, sig } => {
+                write!(f, "no parameter set has a {} bytes public key and a {} bytes signature", pk, sig)
+            }
+            VerifyError::ContextTooLong(len) => write!(f, "context of {} bytes is longer than 255 bytes", len),
+            VerifyError::MalformedEncoding => write!(f, "malformed signature encoding"),
+            VerifyError::ZNormTooLarge => write!(f, "infinity norm of z is at least gamma1 - beta"),
+            VerifyError::TooManyHints => write!(f, "more than omega hints"),
+            VerifyError::ChallengeMismatch => write!(f, "recomputed challenge does not match the signature"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
@@ -0,0 +1,233 @@
+// Keccak-f[1600] on four states at once and SHAKE128/SHAKE256 on four inputs of the same length,
+// for the samplers that expand many polynomials from one seed and different nonces
+// the states are interleaved lane by lane, state[i][n] is the i-th 64 bits lane of the n-th state,
+// so that the AVX2 permutation keeps one lane of all four states in a register
+// the single SHAKE computations still use the sha3 crate
+use zeroize::Zeroize;
+
+pub(crate) const SHAKE128_RATE: usize = 168;
+pub(crate) const SHAKE256_RATE: usize = 136;
+
+pub(crate) type StateX4 = [[u64; 4]; 25];
+
+// the round constants of iota
+pub(crate) const RC: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808A, 0x8000000080008000,
+    0x000000000000808B, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008A, 0x0000000000000088, 0x0000000080008009, 0x000000008000000A,
+    0x000000008000808B, 0x800000000000008B, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800A, 0x800000008000000A,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+
+// one round of Keccak-f[1600] on the lanes $a[0..25], unrolled with constant rotations so that the lanes stay
+// in registers, $xor, $andnot (!x & y) and $rotl! are the operations on a lane of the four states
+macro_rules! keccak_round {
+    ($a:ident, $rc:expr, $xor:path, $andnot:path, $rotl:ident) => {{
+        // theta
+        let c0 = $xor($xor($xor($a[0], $a[5]), $xor($a[10], $a[15])), $a[20]);
+        let c1 = $xor($xor($xor($a[1], $a[6]), $xor($a[11], $a[16])), $a[21]);
+        let c2 = $xor($xor($xor($a[2], $a[7]), $xor($a[12], $a[17])), $a[22]);
+        let c3 = $xor($xor($xor($a[3], $a[8]), $xor($a[13], $a[18])), $a[23]);
+        let c4 = $xor($xor($xor($a[4], $a[9]), $xor($a[14], $a[19])), $a[24]);
+        let d0 = $xor(c4, $rotl!(c1, 1));
+        let d1 = $xor(c0, $rotl!(c2, 1));
+        let d2 = $xor(c1, $rotl!(c3, 1));
+        let d3 = $xor(c2, $rotl!(c4, 1));
+        let d4 = $xor(c3, $rotl!(c0, 1));
+        // rho and pi
+        let b0 = $xor($a[0], d0);
+        let b1 = $rotl!($xor($a[6], d1), 44);
+        let b2 = $rotl!($xor($a[12], d2), 43);
+        let b3 = $rotl!($xor($a[18], d3), 21);
+        let b4 = $rotl!($xor($a[24], d4), 14);
+        let b5 = $rotl!($xor($a[3], d3), 28);
+        let b6 = $rotl!($xor($a[9], d4), 20);
+        let b7 = $rotl!($xor($a[10], d0), 3);
+        let b8 = $rotl!($xor($a[16], d1), 45);
+        let b9 = $rotl!($xor($a[22], d2), 61);
+        let b10 = $rotl!($xor($a[1], d1), 1);
+        let b11 = $rotl!($xor($a[7], d2), 6);
+        let b12 = $rotl!($xor($a[13], d3), 25);
+        let b13 = $rotl!($xor($a[19], d4), 8);
+        let b14 = $rotl!($xor($a[20], d0), 18);
+        let b15 = $rotl!($xor($a[4], d4), 27);
+        let b16 = $rotl!($xor($a[5], d0), 36);
+        let b17 = $rotl!($xor($a[11], d1), 10);
+        let b18 = $rotl!($xor($a[17], d2), 15);
+        let b19 = $rotl!($xor($a[23], d3), 56);
+        let b20 = $rotl!($xor($a[2], d2), 62);
+        let b21 = $rotl!($xor($a[8], d3), 55);
+        let b22 = $rotl!($xor($a[14], d4), 39);
+        let b23 = $rotl!($xor($a[15], d0), 41);
+        let b24 = $rotl!($xor($a[21], d1), 2);
+        // chi and iota
+        $a[0] = $xor($xor(b0, $andnot(b1, b2)), $rc);
+        $a[1] = $xor(b1, $andnot(b2, b3));
+        $a[2] = $xor(b2, $andnot(b3, b4));
+        $a[3] = $xor(b3, $andnot(b4, b0));
+        $a[4] = $xor(b4, $andnot(b0, b1));
+        $a[5] = $xor(b5, $andnot(b6, b7));
+        $a[6] = $xor(b6, $andnot(b7, b8));
+        $a[7] = $xor(b7, $andnot(b8, b9));
+        $a[8] = $xor(b8, $andnot(b9, b5));
+        $a[9] = $xor(b9, $andnot(b5, b6));
+        $a[10] = $xor(b10, $andnot(b11, b12));
+        $a[11] = $xor(b11, $andnot(b12, b13));
+        $a[12] = $xor(b12, $andnot(b13, b14));
+        $a[13] = $xor(b13, $andnot(b14, b10));
+        $a[14] = $xor(b14, $andnot(b10, b11));
+        $a[15] = $xor(b15, $andnot(b16, b17));
+        $a[16] = $xor(b16, $andnot(b17, b18));
+        $a[17] = $xor(b17, $andnot(b18, b19));
+        $a[18] = $xor(b18, $andnot(b19, b15));
+        $a[19] = $xor(b19, $andnot(b15, b16));
+        $a[20] = $xor(b20, $andnot(b21, b22));
+        $a[21] = $xor(b21, $andnot(b22, b23));
+        $a[22] = $xor(b22, $andnot(b23, b24));
+        $a[23] = $xor(b23, $andnot(b24, b20));
+        $a[24] = $xor(b24, $andnot(b20, b21));
+    }};
+}
+pub(crate) use keccak_round;
+
+// uses AVX2 when the CPU has it
+pub(crate) fn keccak_f1600_x4(a: &mut StateX4) {
+    #[cfg(target_arch = "x86_64")]
+    if crate::avx2::available() {
+        // the CPU has AVX2
+        unsafe { crate::avx2::keccak_f1600_x4(a) };
+        return;
+    }
+    keccak_f1600_x4_scalar(a)
+}
+
+// the four states are permuted one after the other, with 64 bits lanes the 25 lanes of one state already
+// use all registers
+pub(crate) fn keccak_f1600_x4_scalar(state: &mut StateX4) {
+    fn xor(x: u64, y: u64) -> u64 {
+        x ^ y
+    }
+    fn andnot(x: u64, y: u64) -> u64 {
+        !x & y
+    }
+    macro_rules! rotl {
+        ($x:expr, $n:literal) => {
+            $x.rotate_left($n)
+        };
+    }
+    for n in 0..4 {
+        let mut a: [u64; 25] = core::array::from_fn(|i| state[i][n]);
+        for rc in RC {
+            keccak_round!(a, rc, xor, andnot, rotl);
+        }
+        for i in 0..25 {
+            state[i][n] = a[i];
+        }
+        a.zeroize();
+    }
+}
+
+// four SHAKE instances with RATE 168 (SHAKE128) or 136 (SHAKE256) bytes, which may hold secret seeds
+// and are wiped when dropped
+pub(crate) struct ShakeX4<const RATE: usize> {
+    state: StateX4,
+}
+
+impl<const RATE: usize> Drop for ShakeX4<RATE> {
+    fn drop(&mut self) {
+        self.state.zeroize();
+    }
+}
+
+impl<const RATE: usize> ShakeX4<RATE> {
+    // absorb and pad four inputs of the same length
+    pub(crate) fn new(inputs: [&[u8]; 4]) -> ShakeX4<RATE> {
+        let len = inputs[0].len();
+        assert!(inputs.iter().all(|input| input.len() == len));
+        let mut shake = ShakeX4 { state: [[0u64; 4]; 25] };
+        let mut pos = 0;
+        while len - pos >= RATE {
+            for (n, input) in inputs.iter().enumerate() {
+                shake.xor_bytes(n, &input[pos..pos + RATE]);
+            }
+            keccak_f1600_x4(&mut shake.state);
+            pos += RATE;
+        }
+        for (n, input) in inputs.iter().enumerate() {
+            shake.xor_bytes(n, &input[pos..]);
+            shake.xor_byte(n, len - pos, 0x1F);
+            shake.xor_byte(n, RATE - 1, 0x80);
+        }
+        shake
+    }
+
+    fn xor_byte(&mut self, n: usize, i: usize, b: u8) {
+        self.state[i / 8][n] ^= (b as u64) << (8 * (i % 8));
+    }
+
+    // xor bytes into the start of the n-th state
+    fn xor_bytes(&mut self, n: usize, bytes: &[u8]) {
+        for (i, &b) in bytes.iter().enumerate() {
+            self.xor_byte(n, i, b);
+        }
+    }
+
+    // squeeze the next out[n].len() / RATE blocks of every instance, all out[n] have the same length,
+    // a multiple of RATE
+    pub(crate) fn squeeze_blocks(&mut self, mut out: [&mut [u8]; 4]) {
+        let len = out[0].len();
+        assert!(len.is_multiple_of(RATE) && out.iter().all(|o| o.len() == len));
+        for block in 0..len / RATE {
+            keccak_f1600_x4(&mut self.state);
+            for (n, o) in out.iter_mut().enumerate() {
+                for (i, b) in o[block * RATE..(block + 1) * RATE].iter_mut().enumerate() {
+                    *b = (self.state[i / 8][n] >> (8 * (i % 8))) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use sha3::digest::{ExtendableOutput, Update, XofReader};
+    use sha3::{Shake128, Shake256};
+
+    fn check<const RATE: usize, H: Default + Update + ExtendableOutput>(rng: &mut StdRng, len: usize) {
+        let mut inputs = [[0u8; 400]; 4];
+        inputs.iter_mut().for_each(|input| rng.fill_bytes(input));
+        let inputs = inputs.each_ref().map(|input| &input[..len]);
+        let mut shake = ShakeX4::<RATE>::new(inputs);
+        let mut out = [[0u8; 3 * 168]; 4];
+        shake.squeeze_blocks(out.each_mut().map(|o| &mut o[..RATE]));
+        shake.squeeze_blocks(out.each_mut().map(|o| &mut o[RATE..3 * RATE]));
+        for (input, out) in inputs.iter().zip(out) {
+            let mut H = H::default();
+            H.update(input);
+            let mut expected = [0u8; 3 * 168];
+            H.finalize_xof().read(&mut expected[..3 * RATE]);
+            assert_eq!(out[..3 * RATE], expected[..3 * RATE], "input of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn test_shake_x4() {
+        let mut rng = StdRng::seed_from_u64(22);
+        for len in [0, 1, 34, 66, 135, 136, 137, 167, 168, 169, 300, 400] {
+            check::<SHAKE128_RATE, Shake128>(&mut rng, len);
+            check::<SHAKE256_RATE, Shake256>(&mut rng, len);
+        }
+    }
+
+    #[test]
+    fn test_keccak_f1600_x4_lanes() {
+        // the zero state of every lane goes to the same known state
+        let mut a = [[0u64; 4]; 25];
+        keccak_f1600_x4_scalar(&mut a);
+        assert_eq!(a[0], [0xF1258F7940E1DDE7; 4]);
+        assert_eq!(a[24], [0xEAF1FF7B5CECA249; 4]);
+    }
+}
@@ -1,10 +1,26 @@
+// the code keeps the notation of the specification, e.g. A, K, H and d
+#![allow(non_snake_case, non_upper_case_globals)]
+#![allow(clippy::needless_range_loop)]
+// without the std feature the crate is no_std, the unit tests always link std
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 pub mod poly;
 pub mod reduce;
 pub mod params;
 mod polyvec;
 mod sample;
 pub mod sign;
+pub mod mldsa;
+pub mod error;
 mod rounding;
 mod pack;
 mod utils;
-mod forgery;
\ No newline at end of file
+mod keccak;
+#[cfg(target_arch = "x86_64")]
+mod avx2;
+mod forgery;
+#[cfg(all(test, feature = "std", target_arch = "x86_64"))]
+mod timing;
\ No newline at end of file
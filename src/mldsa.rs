@@ -0,0 +1,1664 @@
+// ML-DSA as standardized in FIPS 204, sharing the arithmetic of the dilithium round-3 code in sign.rs
+// the differences are a 64 bytes tr, the seed absorbed together with k and l, a lambda/4 bytes c tilde,
+// 32 bytes of rnd in rhoprime and the message framed as 0 || |ctx| || ctx || m
+use crate::error::{Error, VerifyError};
+#[cfg(feature = "alloc")]
+use crate::params::get_sizes;
+use crate::params::Mode;
+use crate::sign::{
+    compute_tr, finalize_mu, key_pair_into_internal, mu_hasher, sign_into_internal, verify_internal,
+    verify_internal_detailed, verify_mu,
+};
+#[cfg(feature = "alloc")]
+use crate::sign::{
+    check_signature, check_sk, key_pair_internal, prepare_pk, prepare_sk, public_key_internal, sign_internal,
+    sign_mu, sign_mu_committed, sign_mu_prepared, verify_mu_prepared, Commitments, PreparedPk, PreparedSk,
+};
+#[cfg(feature = "std")]
+use crate::sign::verify_batch_internal;
+use crate::utils::ArrayBuf;
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "alloc")]
+use rand_core::CryptoRngCore;
+#[cfg(feature = "alloc")]
+use sha2::{Digest, Sha256, Sha512};
+use sha3::digest::Update;
+#[cfg(feature = "alloc")]
+use sha3::{
+    digest::{ExtendableOutput, XofReader},
+    Sha3_256, Sha3_512, Shake128, Shake256,
+};
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "alloc")]
+use zeroize::{ZeroizeOnDrop, Zeroizing};
+
+// the deterministic variant of ML-DSA.Sign uses an all-zero rnd
+#[cfg(feature = "alloc")]
+const RND_ZERO: [u8; 32] = [0u8; 32];
+
+// 1 || |ctx| || ctx || OID is the longest pre
+type Pre = ArrayBuf<{ 2 + 255 + 11 }>;
+
+// pre = 0 || |ctx| || ctx, None if ctx is longer than 255 bytes
+fn frame_ctx(ctx: &[u8]) -> Option<Pre> {
+    if ctx.len() > 255 {
+        return None;
+    }
+    let mut pre = Pre::zeroed(0);
+    pre.extend_from_slice(&[0u8, ctx.len() as u8]);
+    pre.extend_from_slice(ctx);
+    Some(pre)
+}
+
+// the pre-hash functions of HashML-DSA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+    Sha3_256,
+    Sha3_512,
+    // 256 bits of output
+    Shake128,
+    // 512 bits of output
+    Shake256,
+}
+
+impl HashAlgorithm {
+    // the DER encoding of the OID of the hash function, 2.16.840.1.101.3.4.2.x
+    pub fn oid(&self) -> [u8; 11] {
+        let x = match self {
+            HashAlgorithm::Sha256 => 0x01,
+            HashAlgorithm::Sha512 => 0x03,
+            HashAlgorithm::Sha3_256 => 0x08,
+            HashAlgorithm::Sha3_512 => 0x0a,
+            HashAlgorithm::Shake128 => 0x0b,
+            HashAlgorithm::Shake256 => 0x0c,
+        };
+        [0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, x]
+    }
+
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Sha3_256 | HashAlgorithm::Shake128 => 32,
+            HashAlgorithm::Sha512 | HashAlgorithm::Sha3_512 | HashAlgorithm::Shake256 => 64,
+        }
+    }
+
+    // PH(M)
+    #[cfg(feature = "alloc")]
+    pub fn digest(&self, m: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(m).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(m).to_vec(),
+            HashAlgorithm::Sha3_256 => Sha3_256::digest(m).to_vec(),
+            HashAlgorithm::Sha3_512 => Sha3_512::digest(m).to_vec(),
+            HashAlgorithm::Shake128 => {
+                let mut H = Shake128::default();
+                H.update(m);
+                let mut ph = vec![0u8; 32];
+                H.finalize_xof().read(&mut ph);
+                ph
+            }
+            HashAlgorithm::Shake256 => {
+                let mut H = Shake256::default();
+                H.update(m);
+                let mut ph = vec![0u8; 64];
+                H.finalize_xof().read(&mut ph);
+                ph
+            }
+        }
+    }
+}
+
+// HashML-DSA signs 1 || |ctx| || ctx || OID || PH(M), returns pre = 1 || |ctx| || ctx || OID
+fn frame_prehash(digest: &[u8], ctx: &[u8], ph: HashAlgorithm) -> Result<Pre, Error> {
+    if ctx.len() > 255 {
+        return Err(Error::ContextTooLong(ctx.len()));
+    }
+    if digest.len() != ph.digest_len() {
+        return Err(Error::InvalidDigestLength(digest.len()));
+    }
+    let mut pre = Pre::zeroed(0);
+    pre.extend_from_slice(&[1u8, ctx.len() as u8]);
+    pre.extend_from_slice(ctx);
+    pre.extend_from_slice(&ph.oid());
+    Ok(pre)
+}
+
+#[cfg(feature = "alloc")]
+pub fn key_pair(seed: &[u8; 32], security_level: u8) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), Error> {
+    key_pair_internal(seed, security_level, Mode::MlDsa)
+}
+
+// key_pair into caller provided buffers of the sizes given by get_sizes, without alloc
+// A is sampled as it is multiplied and never held, the stack needs about 42 KB at level 2 and 64 KB at level 5
+pub fn key_pair_into(seed: &[u8; 32], security_level: u8, pk: &mut [u8], sk: &mut [u8]) -> Result<(), Error> {
+    key_pair_into_internal(seed, security_level, Mode::MlDsa, pk, sk)
+}
+
+// sign_with_rnd into a caller provided buffer of the size of a signature, without alloc
+// the packed key is read in place and A is sampled again for every attempt, which takes about 46 KB of stack
+// at level 2 and 64 KB at level 5 but more time than signing with alloc
+// an all-zero rnd gives the deterministic variant
+pub fn sign_into(
+    sk: &[u8],
+    m: &[u8],
+    ctx: &[u8],
+    rnd: &[u8; 32],
+    security_level: u8,
+    sig: &mut [u8],
+) -> Result<(), Error> {
+    let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+    sign_into_internal(sk, &pre, m, rnd, security_level, Mode::MlDsa, sig)
+}
+
+// the deterministic variant
+// a ctx longer than 255 bytes, an unsupported level or an sk of the wrong length is an Error as in sign_into
+#[cfg(feature = "alloc")]
+pub fn sign(sk: &[u8], m: &[u8], ctx: &[u8], security_level: u8) -> Result<Vec<u8>, Error> {
+    sign_with_rnd(sk, m, ctx, &RND_ZERO, security_level)
+}
+
+// the hedged variant, 32 fresh bytes from rng are mixed into rhoprime
+// so that a fault or a side channel on one signature does not reveal the key
+#[cfg(feature = "alloc")]
+pub fn sign_randomized(
+    sk: &[u8],
+    m: &[u8],
+    ctx: &[u8],
+    security_level: u8,
+    rng: &mut impl CryptoRngCore,
+) -> Result<Vec<u8>, Error> {
+    let mut rnd = Zeroizing::new([0u8; 32]);
+    rng.fill_bytes(rnd.as_mut());
+    sign_with_rnd(sk, m, ctx, &rnd, security_level)
+}
+
+// rhoprime = H(K || rnd || mu) with a caller supplied rnd, e.g. to reproduce a hedged signature in tests
+#[cfg(feature = "alloc")]
+pub fn sign_with_rnd(
+    sk: &[u8],
+    m: &[u8],
+    ctx: &[u8],
+    rnd: &[u8; 32],
+    security_level: u8,
+) -> Result<Vec<u8>, Error> {
+    let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+    sign_internal(sk, &pre, m, rnd, security_level, Mode::MlDsa)
+}
+
+// the security level is inferred from the lengths of pk and sig, a ctx longer than 255 bytes is rejected
+pub fn verify(sig: &[u8], pk: &[u8], m: &[u8], ctx: &[u8]) -> bool {
+    match frame_ctx(ctx) {
+        Some(pre) => verify_internal(sig, pk, &pre, m, Mode::MlDsa),
+        None => false,
+    }
+}
+
+// verify, reporting why a signature is rejected
+pub fn verify_detailed(sig: &[u8], pk: &[u8], m: &[u8], ctx: &[u8]) -> Result<(), VerifyError> {
+    let pre = frame_ctx(ctx).ok_or(VerifyError::ContextTooLong(ctx.len()))?;
+    verify_internal_detailed(sig, pk, &pre, m, Mode::MlDsa)
+}
+
+// verify many (sig, pk, m) triples signed with the same ctx on all cores, see sign::verify_batch
+#[cfg(feature = "std")]
+pub fn verify_batch(items: &[(&[u8], &[u8], &[u8])], ctx: &[u8]) -> Vec<Result<(), VerifyError>> {
+    match frame_ctx(ctx) {
+        Some(pre) => verify_batch_internal(items, &pre, Mode::MlDsa),
+        None => vec![Err(VerifyError::ContextTooLong(ctx.len())); items.len()],
+    }
+}
+
+// recompute the public key of a secret key, which also checks that the secret key is consistent
+#[cfg(feature = "alloc")]
+pub fn public_key(sk: &[u8]) -> Result<Vec<u8>, Error> {
+    public_key_internal(sk, Mode::MlDsa)
+}
+
+// external mu: mu = H(H(pk) || 0 || |ctx| || ctx || m) is computed by a party holding the public key and
+// the message, the signer runs the rejection loop on mu alone and never sees the message
+pub fn compute_mu(pk: &[u8], m: &[u8], ctx: &[u8]) -> Result<[u8; 64], Error> {
+    let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+    let mut H = mu_hasher(&compute_tr(pk, Mode::MlDsa), &pre);
+    H.update(m);
+    Ok(finalize_mu(H))
+}
+
+// the deterministic variant
+#[cfg(feature = "alloc")]
+pub fn sign_external_mu(sk: &[u8], mu: &[u8; 64], security_level: u8) -> Result<Vec<u8>, Error> {
+    sign_mu(sk, mu, &RND_ZERO, security_level, Mode::MlDsa)
+}
+
+// the hedged variant
+#[cfg(feature = "alloc")]
+pub fn sign_external_mu_randomized(
+    sk: &[u8],
+    mu: &[u8; 64],
+    security_level: u8,
+    rng: &mut impl CryptoRngCore,
+) -> Result<Vec<u8>, Error> {
+    let mut rnd = Zeroizing::new([0u8; 32]);
+    rng.fill_bytes(rnd.as_mut());
+    sign_mu(sk, mu, &rnd[..], security_level, Mode::MlDsa)
+}
+
+pub fn verify_external_mu(sig: &[u8], pk: &[u8], mu: &[u8; 64]) -> bool {
+    verify_mu(sig, pk, mu, Mode::MlDsa)
+}
+
+// HashML-DSA over a digest = ph.digest(m), which can be computed outside of the signer
+// the deterministic variant, a ctx longer than 255 bytes or a digest of the wrong length is an Error
+#[cfg(feature = "alloc")]
+pub fn hash_sign(
+    sk: &[u8],
+    digest: &[u8],
+    ctx: &[u8],
+    ph: HashAlgorithm,
+    security_level: u8,
+) -> Result<Vec<u8>, Error> {
+    hash_sign_with_rnd(sk, digest, ctx, ph, &RND_ZERO, security_level)
+}
+
+// the hedged variant of hash_sign
+#[cfg(feature = "alloc")]
+pub fn hash_sign_randomized(
+    sk: &[u8],
+    digest: &[u8],
+    ctx: &[u8],
+    ph: HashAlgorithm,
+    security_level: u8,
+    rng: &mut impl CryptoRngCore,
+) -> Result<Vec<u8>, Error> {
+    let mut rnd = Zeroizing::new([0u8; 32]);
+    rng.fill_bytes(rnd.as_mut());
+    hash_sign_with_rnd(sk, digest, ctx, ph, &rnd, security_level)
+}
+
+#[cfg(feature = "alloc")]
+pub fn hash_sign_with_rnd(
+    sk: &[u8],
+    digest: &[u8],
+    ctx: &[u8],
+    ph: HashAlgorithm,
+    rnd: &[u8; 32],
+    security_level: u8,
+) -> Result<Vec<u8>, Error> {
+    let pre = frame_prehash(digest, ctx, ph)?;
+    sign_internal(sk, &pre, digest, rnd, security_level, Mode::MlDsa)
+}
+
+pub fn hash_verify(sig: &[u8], pk: &[u8], digest: &[u8], ctx: &[u8], ph: HashAlgorithm) -> bool {
+    match frame_prehash(digest, ctx, ph) {
+        Ok(pre) => verify_internal(sig, pk, &pre, digest, Mode::MlDsa),
+        Err(_) => false,
+    }
+}
+
+// the security level whose encoding selected by size has the given length
+#[cfg(feature = "alloc")]
+fn find_level(len: usize, size: fn((usize, usize, usize)) -> usize) -> Result<u8, Error> {
+    [2, 3, 5]
+        .into_iter()
+        .find(|&level| size(get_sizes(level, Mode::MlDsa)) == len)
+        .ok_or(Error::InvalidLength(len))
+}
+
+// typed keys and signatures carry their security level, constructing one checks the encoding
+// so that signing and verification with them never panic
+// a signing key remembers the 32 bytes seed it was expanded from, which is enough to store or transmit it,
+// keys loaded from the expanded form have no seed
+// the seed and the expanded form are wiped when the key is dropped
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub struct SigningKey {
+    security_level: u8,
+    seed: Option<Zeroizing<[u8; 32]>>,
+    bytes: Zeroizing<Vec<u8>>,
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyingKey {
+    security_level: u8,
+    bytes: Vec<u8>,
+}
+
+// a signing key with A, K, tr and s1, s2, t0 computed once, so that signing many messages
+// skips unpacking the key and expanding A
+// s1, s2 and t0 are in NTT form at level 5 and in normal form at levels 2 and 3,
+// whose parameter sets multiply them with a sparse challenge
+// it is much larger than the packed secret key, see the readme for the sizes and the speedup
+// the secret parts are wiped when it is dropped
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub struct PreparedSigningKey {
+    security_level: u8,
+    tr: Vec<u8>,
+    sk: PreparedSk,
+}
+
+// offline/online signing: the commitments y, w = A*y and w1 of the rejection loop do not depend on the message,
+// so precompute fills a pool of them ahead of time and sign only computes mu, the challenge, z and the hints
+// each commitment is used at most once, a rejected attempt takes the next one and an empty pool falls back
+// to the commitments of a hedged signature
+// the signatures are valid hedged ML-DSA signatures, but their y comes from H(K || rnd) instead of H(K || rnd || mu)
+// the pool trades memory for latency, a signature uses several commitments on average
+// it is not Clone, two copies of the pool would use the same commitments for two signatures and reveal the key
+#[cfg(feature = "alloc")]
+pub struct OnlineSigner {
+    key: PreparedSigningKey,
+    pool: Commitments,
+}
+
+// a verifying key with A, NTT(t1 * 2^d) and tr computed once, so that verifying many signatures
+// of the same signer only does the work that depends on the signature
+// it is much larger than the packed public key, see the readme for the sizes
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub struct PreparedVerifyingKey {
+    security_level: u8,
+    tr: Vec<u8>,
+    pk: PreparedPk,
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    security_level: u8,
+    bytes: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+pub fn generate(seed: &[u8; 32], security_level: u8) -> Result<(SigningKey, VerifyingKey), Error> {
+    let (pk, sk) = key_pair(seed, security_level)?;
+    Ok((
+        SigningKey { security_level, seed: Some(Zeroizing::new(*seed)), bytes: sk },
+        VerifyingKey { security_level, bytes: pk },
+    ))
+}
+
+// two keys are equal if their expanded forms are, whether the seed is known or not
+#[cfg(feature = "alloc")]
+impl PartialEq for SigningKey {
+    fn eq(&self, other: &SigningKey) -> bool {
+        self.security_level == other.security_level && self.bytes == other.bytes
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Eq for SigningKey {}
+
+#[cfg(feature = "alloc")]
+impl ZeroizeOnDrop for SigningKey {}
+
+#[cfg(feature = "alloc")]
+impl ZeroizeOnDrop for PreparedSigningKey {}
+
+#[cfg(feature = "alloc")]
+impl ZeroizeOnDrop for OnlineSigner {}
+
+#[cfg(feature = "alloc")]
+impl SigningKey {
+    // expand a seed into the signing key, the seed does not encode the security level
+    pub fn from_seed(seed: &[u8; 32], security_level: u8) -> Result<SigningKey, Error> {
+        generate(seed, security_level).map(|(sk, _)| sk)
+    }
+
+    // load a key stored in both forms, the expanded form must be the expansion of the seed
+    pub fn from_seed_and_bytes(seed: &[u8; 32], bytes: &[u8]) -> Result<SigningKey, Error> {
+        let security_level = find_level(bytes.len(), |(_, sk, _)| sk)?;
+        let sk = SigningKey::from_seed(seed, security_level)?;
+        if sk.bytes[..] != *bytes {
+            return Err(Error::InconsistentKey);
+        }
+        Ok(sk)
+    }
+
+    // the matching verifying key, or an error describing why the expanded form is not consistent
+    pub fn verifying_key(&self) -> Result<VerifyingKey, Error> {
+        let bytes = public_key_internal(&self.bytes, Mode::MlDsa)?;
+        Ok(VerifyingKey { security_level: self.security_level, bytes })
+    }
+
+    // the 32 bytes seed, None if the key was loaded from its expanded form
+    pub fn seed(&self) -> Option<Zeroizing<[u8; 32]>> {
+        self.seed.clone()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SigningKey, Error> {
+        let security_level = find_level(bytes.len(), |(_, sk, _)| sk)?;
+        if !check_sk(bytes, security_level, Mode::MlDsa) {
+            return Err(Error::MalformedEncoding);
+        }
+        Ok(SigningKey { security_level, seed: None, bytes: Zeroizing::new(bytes.to_vec()) })
+    }
+
+    // the expanded form (rho, K, tr, s1, s2, t0)
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        self.bytes.clone()
+    }
+
+    pub fn security_level(&self) -> u8 {
+        self.security_level
+    }
+
+    // deterministic signing
+    pub fn sign(&self, m: &[u8], ctx: &[u8]) -> Result<Signature, Error> {
+        self.sign_with_rnd(m, ctx, &RND_ZERO)
+    }
+
+    // hedged signing with 32 fresh bytes from rng
+    pub fn sign_randomized(
+        &self,
+        m: &[u8],
+        ctx: &[u8],
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Signature, Error> {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.sign_with_rnd(m, ctx, &rnd)
+    }
+
+    // signing on a mu computed by VerifyingKey::compute_mu
+    pub fn sign_external_mu(&self, mu: &[u8; 64]) -> Signature {
+        self.sign_mu_with_rnd(mu, &RND_ZERO)
+    }
+
+    pub fn sign_external_mu_randomized(&self, mu: &[u8; 64], rng: &mut impl CryptoRngCore) -> Signature {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.sign_mu_with_rnd(mu, &rnd)
+    }
+
+    // the level and the length of the bytes were checked when the key was built
+    fn sign_mu_with_rnd(&self, mu: &[u8; 64], rnd: &[u8; 32]) -> Signature {
+        let bytes = sign_mu(&self.bytes, mu, rnd, self.security_level, Mode::MlDsa)
+            .expect("a SigningKey holds a secret key of its level");
+        Signature { security_level: self.security_level, bytes }
+    }
+
+    // HashML-DSA over a digest = ph.digest(m)
+    pub fn hash_sign(&self, digest: &[u8], ctx: &[u8], ph: HashAlgorithm) -> Result<Signature, Error> {
+        self.hash_sign_with_rnd(digest, ctx, ph, &RND_ZERO)
+    }
+
+    pub fn hash_sign_randomized(
+        &self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Signature, Error> {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.hash_sign_with_rnd(digest, ctx, ph, &rnd)
+    }
+
+    pub fn hash_sign_with_rnd(
+        &self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        rnd: &[u8; 32],
+    ) -> Result<Signature, Error> {
+        let pre = frame_prehash(digest, ctx, ph)?;
+        let bytes = sign_internal(&self.bytes, &pre, digest, rnd, self.security_level, Mode::MlDsa)?;
+        Ok(Signature { security_level: self.security_level, bytes })
+    }
+
+    // a signer absorbing the message in chunks
+    pub fn signer(&self, ctx: &[u8]) -> Result<Signer<'_>, Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        Ok(Signer { key: self, H: mu_hasher(&self.bytes[64..128], &pre) })
+    }
+
+    pub fn sign_with_rnd(&self, m: &[u8], ctx: &[u8], rnd: &[u8; 32]) -> Result<Signature, Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        let bytes = sign_internal(&self.bytes, &pre, m, rnd, self.security_level, Mode::MlDsa)?;
+        Ok(Signature { security_level: self.security_level, bytes })
+    }
+
+    pub fn prepare(&self) -> PreparedSigningKey {
+        PreparedSigningKey {
+            security_level: self.security_level,
+            tr: self.bytes[64..128].to_vec(),
+            sk: prepare_sk(&self.bytes, self.security_level, Mode::MlDsa),
+        }
+    }
+}
+
+// the secret key is not printed
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("security_level", &self.security_level)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PreparedSigningKey {
+    pub fn security_level(&self) -> u8 {
+        self.security_level
+    }
+
+    // deterministic signing
+    pub fn sign(&self, m: &[u8], ctx: &[u8]) -> Result<Signature, Error> {
+        self.sign_with_rnd(m, ctx, &RND_ZERO)
+    }
+
+    // hedged signing with 32 fresh bytes from rng
+    pub fn sign_randomized(
+        &self,
+        m: &[u8],
+        ctx: &[u8],
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Signature, Error> {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.sign_with_rnd(m, ctx, &rnd)
+    }
+
+    pub fn sign_with_rnd(&self, m: &[u8], ctx: &[u8], rnd: &[u8; 32]) -> Result<Signature, Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        Ok(self.sign_framed(&pre, m, rnd))
+    }
+
+    // signing on a mu computed by VerifyingKey::compute_mu
+    pub fn sign_external_mu(&self, mu: &[u8; 64]) -> Signature {
+        self.sign_mu_with_rnd(mu, &RND_ZERO)
+    }
+
+    pub fn sign_external_mu_randomized(&self, mu: &[u8; 64], rng: &mut impl CryptoRngCore) -> Signature {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.sign_mu_with_rnd(mu, &rnd)
+    }
+
+    // HashML-DSA over a digest = ph.digest(m)
+    pub fn hash_sign(&self, digest: &[u8], ctx: &[u8], ph: HashAlgorithm) -> Result<Signature, Error> {
+        self.hash_sign_with_rnd(digest, ctx, ph, &RND_ZERO)
+    }
+
+    pub fn hash_sign_randomized(
+        &self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Signature, Error> {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.hash_sign_with_rnd(digest, ctx, ph, &rnd)
+    }
+
+    pub fn hash_sign_with_rnd(
+        &self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        rnd: &[u8; 32],
+    ) -> Result<Signature, Error> {
+        let pre = frame_prehash(digest, ctx, ph)?;
+        Ok(self.sign_framed(&pre, digest, rnd))
+    }
+
+    // mu = H(tr || pre || m)
+    fn sign_framed(&self, pre: &[u8], m: &[u8], rnd: &[u8; 32]) -> Signature {
+        let mut H = mu_hasher(&self.tr, pre);
+        H.update(m);
+        self.sign_mu_with_rnd(&finalize_mu(H), rnd)
+    }
+
+    fn sign_mu_with_rnd(&self, mu: &[u8; 64], rnd: &[u8; 32]) -> Signature {
+        let bytes = sign_mu_prepared(&self.sk, mu, rnd, Mode::MlDsa);
+        Signature { security_level: self.security_level, bytes }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl OnlineSigner {
+    pub fn new(key: PreparedSigningKey) -> OnlineSigner {
+        let pool = Commitments::new(&key.sk);
+        OnlineSigner { key, pool }
+    }
+
+    pub fn security_level(&self) -> u8 {
+        self.key.security_level
+    }
+
+    // the number of precomputed commitments left
+    pub fn available(&self) -> usize {
+        self.pool.len()
+    }
+
+    // add n commitments to the pool, each from 32 fresh bytes of rng
+    pub fn precompute(&mut self, n: usize, rng: &mut impl CryptoRngCore) {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        self.pool.reserve(n);
+        for _ in 0..n {
+            rng.fill_bytes(rnd.as_mut());
+            self.pool.push(&self.key.sk, &rnd);
+        }
+    }
+
+    // rng is only used for fresh commitments once the pool is empty
+    pub fn sign(&mut self, m: &[u8], ctx: &[u8], rng: &mut impl CryptoRngCore) -> Result<Signature, Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        let mut H = mu_hasher(&self.key.tr, &pre);
+        H.update(m);
+        Ok(self.sign_external_mu(&finalize_mu(H), rng))
+    }
+
+    pub fn hash_sign(
+        &mut self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Signature, Error> {
+        let mut H = mu_hasher(&self.key.tr, &frame_prehash(digest, ctx, ph)?);
+        H.update(digest);
+        Ok(self.sign_external_mu(&finalize_mu(H), rng))
+    }
+
+    pub fn sign_external_mu(&mut self, mu: &[u8; 64], rng: &mut impl CryptoRngCore) -> Signature {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        let bytes = sign_mu_committed(&self.key.sk, &mut self.pool, mu, &rnd[..], Mode::MlDsa);
+        Signature { security_level: self.key.security_level, bytes }
+    }
+}
+
+// the commitments are not printed
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for OnlineSigner {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("OnlineSigner")
+            .field("security_level", &self.key.security_level)
+            .field("available", &self.pool.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// the expanded secret key is not printed
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for PreparedSigningKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PreparedSigningKey")
+            .field("security_level", &self.security_level)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl VerifyingKey {
+    // every t1 and rho is a valid public key, only the length is checked
+    pub fn from_bytes(bytes: &[u8]) -> Result<VerifyingKey, Error> {
+        let security_level = find_level(bytes.len(), |(pk, _, _)| pk)?;
+        Ok(VerifyingKey { security_level, bytes: bytes.to_vec() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    pub fn security_level(&self) -> u8 {
+        self.security_level
+    }
+
+    // mu = H(tr || 0 || |ctx| || ctx || m) for external mu signing
+    pub fn compute_mu(&self, m: &[u8], ctx: &[u8]) -> Result<[u8; 64], Error> {
+        let mut verifier = self.verifier(ctx)?;
+        verifier.update(m);
+        Ok(finalize_mu(verifier.H))
+    }
+
+    pub fn verify_external_mu(&self, mu: &[u8; 64], sig: &Signature) -> Result<(), Error> {
+        if sig.security_level != self.security_level || !verify_mu(&sig.bytes, &self.bytes, mu, Mode::MlDsa) {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    pub fn hash_verify(
+        &self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        sig: &Signature,
+    ) -> Result<(), Error> {
+        let pre = frame_prehash(digest, ctx, ph)?;
+        if sig.security_level != self.security_level
+            || !verify_internal(&sig.bytes, &self.bytes, &pre, digest, Mode::MlDsa)
+        {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    // a verifier absorbing the message in chunks
+    pub fn verifier(&self, ctx: &[u8]) -> Result<Verifier<'_>, Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        Ok(Verifier { key: self, H: mu_hasher(&compute_tr(&self.bytes, Mode::MlDsa), &pre) })
+    }
+
+    pub fn verify(&self, m: &[u8], ctx: &[u8], sig: &Signature) -> Result<(), Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        if sig.security_level != self.security_level
+            || !verify_internal(&sig.bytes, &self.bytes, &pre, m, Mode::MlDsa)
+        {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    pub fn prepare(&self) -> PreparedVerifyingKey {
+        PreparedVerifyingKey {
+            security_level: self.security_level,
+            tr: compute_tr(&self.bytes, Mode::MlDsa).to_vec(),
+            pk: prepare_pk(&self.bytes, self.security_level),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl PreparedVerifyingKey {
+    pub fn security_level(&self) -> u8 {
+        self.security_level
+    }
+
+    // mu = H(tr || 0 || |ctx| || ctx || m) for external mu signing
+    pub fn compute_mu(&self, m: &[u8], ctx: &[u8]) -> Result<[u8; 64], Error> {
+        let pre = frame_ctx(ctx).ok_or(Error::ContextTooLong(ctx.len()))?;
+        let mut H = mu_hasher(&self.tr, &pre);
+        H.update(m);
+        Ok(finalize_mu(H))
+    }
+
+    pub fn verify_external_mu(&self, mu: &[u8; 64], sig: &Signature) -> Result<(), Error> {
+        if sig.security_level != self.security_level
+            || verify_mu_prepared(&sig.bytes, &self.pk, mu, Mode::MlDsa).is_err()
+        {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    pub fn hash_verify(
+        &self,
+        digest: &[u8],
+        ctx: &[u8],
+        ph: HashAlgorithm,
+        sig: &Signature,
+    ) -> Result<(), Error> {
+        let mut H = mu_hasher(&self.tr, &frame_prehash(digest, ctx, ph)?);
+        H.update(digest);
+        self.verify_external_mu(&finalize_mu(H), sig)
+    }
+
+    pub fn verify(&self, m: &[u8], ctx: &[u8], sig: &Signature) -> Result<(), Error> {
+        self.verify_external_mu(&self.compute_mu(m, ctx)?, sig)
+    }
+}
+
+// the expanded matrix is too large to print
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for PreparedVerifyingKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PreparedVerifyingKey")
+            .field("security_level", &self.security_level)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Signature {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, Error> {
+        let security_level = find_level(bytes.len(), |(_, _, sig)| sig)?;
+        check_signature(bytes, security_level, Mode::MlDsa)?;
+        Ok(Signature { security_level, bytes: bytes.to_vec() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    pub fn security_level(&self) -> u8 {
+        self.security_level
+    }
+}
+
+// streaming signing and verification, the message is absorbed into mu = H(tr || 0 || |ctx| || ctx || m)
+// chunk by chunk, so the signature of a large file never needs the whole file in memory
+// both implement io::Write, so io::copy can feed them from any io::Read
+#[cfg(feature = "alloc")]
+pub struct Signer<'a> {
+    key: &'a SigningKey,
+    H: Shake256,
+}
+
+#[cfg(feature = "alloc")]
+pub struct Verifier<'a> {
+    key: &'a VerifyingKey,
+    H: Shake256,
+}
+
+#[cfg(feature = "alloc")]
+impl Signer<'_> {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.H.update(chunk);
+    }
+
+    // absorb everything from reader, returns the number of bytes read
+    #[cfg(feature = "std")]
+    pub fn update_reader(&mut self, reader: &mut impl io::Read) -> io::Result<u64> {
+        io::copy(reader, self)
+    }
+
+    // deterministic signing
+    pub fn finalize(self) -> Signature {
+        self.finalize_with_rnd(&RND_ZERO)
+    }
+
+    // hedged signing with 32 fresh bytes from rng
+    pub fn finalize_randomized(self, rng: &mut impl CryptoRngCore) -> Signature {
+        let mut rnd = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(rnd.as_mut());
+        self.finalize_with_rnd(&rnd)
+    }
+
+    pub fn finalize_with_rnd(self, rnd: &[u8; 32]) -> Signature {
+        self.key.sign_mu_with_rnd(&finalize_mu(self.H), rnd)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Verifier<'_> {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.H.update(chunk);
+    }
+
+    // absorb everything from reader, returns the number of bytes read
+    #[cfg(feature = "std")]
+    pub fn update_reader(&mut self, reader: &mut impl io::Read) -> io::Result<u64> {
+        io::copy(reader, self)
+    }
+
+    pub fn finalize(self, sig: &Signature) -> Result<(), Error> {
+        if sig.security_level != self.key.security_level
+            || !verify_mu(&sig.bytes, &self.key.bytes, &finalize_mu(self.H), Mode::MlDsa)
+        {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for Signer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for Verifier<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use sha3::{
+        digest::{ExtendableOutput, Update, XofReader},
+        Shake256,
+    };
+
+    const SEED: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+        0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
+        0x1c, 0x1d, 0x1e, 0x1f,
+    ];
+
+    const MSG: &[u8] = b"message to sign";
+
+    const CTX: &[u8] = b"context";
+
+    fn shake256_digest(bytes: &[u8]) -> [u8; 32] {
+        let mut H = Shake256::default();
+        H.update(bytes);
+        let mut reader = H.finalize_xof();
+        let mut out = [0u8; 32];
+        reader.read(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_ml_dsa_44() {
+        // SHAKE256 digests of the pk, sk and signature produced by the FIPS 204 reference implementation
+        let pk_digest = [
+            0x32, 0x9a, 0x07, 0xb1, 0xfa, 0xbb, 0x48, 0xf5, 0x2a, 0x30, 0x9f, 0x11, 0xa1, 0x89,
+            0x8f, 0x84, 0x8e, 0x23, 0x22, 0xff, 0xe6, 0x23, 0xec, 0x81, 0x0d, 0xb3, 0xbe, 0xe3,
+            0x36, 0x85, 0x85, 0x4a,
+        ];
+        let sk_digest = [
+            0xea, 0xa4, 0xbd, 0x0a, 0x70, 0x64, 0xe1, 0xae, 0xf9, 0x62, 0x3b, 0xfa, 0x4c, 0x02,
+            0x09, 0xa9, 0x88, 0x84, 0xf0, 0x85, 0x58, 0xbb, 0xe9, 0x34, 0xda, 0xef, 0xb1, 0x6a,
+            0x04, 0x9b, 0xa2, 0x8a,
+        ];
+        let sig_digest = [
+            0x20, 0xef, 0x01, 0x8b, 0x77, 0xdb, 0x6e, 0x09, 0xd1, 0xb2, 0xfd, 0xb4, 0x52, 0x2a,
+            0x7e, 0x21, 0x5c, 0xb0, 0x3d, 0x05, 0xe5, 0xd2, 0x7d, 0xca, 0x95, 0xe3, 0x34, 0xc7,
+            0xbe, 0x65, 0x65, 0x7b,
+        ];
+
+        let (pk, sk) = super::key_pair(&SEED, 2).unwrap();
+        let sig = super::sign(&sk, MSG, CTX, 2).unwrap();
+        assert_eq!(shake256_digest(&pk), pk_digest);
+        assert_eq!(shake256_digest(&sk), sk_digest);
+        assert_eq!(shake256_digest(&sig), sig_digest);
+        assert!(super::verify(&sig, &pk, MSG, CTX));
+    }
+
+    #[test]
+    fn test_ml_dsa_65() {
+        // SHAKE256 digests of the pk, sk and signature produced by the FIPS 204 reference implementation
+        let pk_digest = [
+            0x1b, 0x05, 0x63, 0xe3, 0xcd, 0x33, 0x46, 0x14, 0x9c, 0x8c, 0x9e, 0xbc, 0xf2, 0x3b,
+            0x0a, 0x4e, 0x5a, 0x90, 0x0e, 0xea, 0x9c, 0x65, 0x62, 0x79, 0x0a, 0x7c, 0x63, 0xe3,
+            0x86, 0x63, 0xda, 0xa2,
+        ];
+        let sk_digest = [
+            0xfe, 0x87, 0x05, 0x8d, 0x58, 0x2b, 0x89, 0xa4, 0x7b, 0xb1, 0x8b, 0x7a, 0xd9, 0xfd,
+            0x26, 0x36, 0xe2, 0xc1, 0x8a, 0xf0, 0x39, 0x0c, 0x7a, 0xd6, 0x10, 0x1a, 0xa6, 0xf7,
+            0x8b, 0x1e, 0xe9, 0x4e,
+        ];
+        let sig_digest = [
+            0x17, 0x35, 0x86, 0x07, 0xa2, 0x1d, 0xe0, 0x3e, 0x7c, 0xe6, 0xcc, 0x6c, 0x4d, 0xe0,
+            0x8a, 0xd5, 0x56, 0xcf, 0x08, 0x08, 0xf3, 0xe8, 0xe3, 0xbe, 0x3d, 0x68, 0xb3, 0x79,
+            0xe6, 0xe9, 0x9f, 0x69,
+        ];
+
+        let (pk, sk) = super::key_pair(&SEED, 3).unwrap();
+        let sig = super::sign(&sk, MSG, CTX, 3).unwrap();
+        assert_eq!(shake256_digest(&pk), pk_digest);
+        assert_eq!(shake256_digest(&sk), sk_digest);
+        assert_eq!(shake256_digest(&sig), sig_digest);
+        assert!(super::verify(&sig, &pk, MSG, CTX));
+    }
+
+    #[test]
+    fn test_ml_dsa_87() {
+        // SHAKE256 digests of the pk, sk and signature produced by the FIPS 204 reference implementation
+        let pk_digest = [
+            0x89, 0x88, 0x67, 0x50, 0xb5, 0x7c, 0x24, 0xdb, 0x3f, 0xc0, 0x12, 0xe6, 0x1e, 0xde,
+            0x59, 0x75, 0x33, 0x37, 0x37, 0x4f, 0xa7, 0x12, 0x49, 0x91, 0x54, 0x9a, 0xf2, 0x43,
+            0x49, 0x6d, 0x06, 0x37,
+        ];
+        let sk_digest = [
+            0xc0, 0x69, 0x33, 0xa3, 0x62, 0xa7, 0xd0, 0xda, 0x22, 0x79, 0xa3, 0xd7, 0x0e, 0xdb,
+            0x9a, 0xcc, 0x56, 0x07, 0x03, 0xd5, 0x34, 0xf5, 0x72, 0x2f, 0x54, 0xe2, 0xc8, 0xc4,
+            0x18, 0x31, 0x1d, 0x42,
+        ];
+        let sig_digest = [
+            0x78, 0xe2, 0x6d, 0x4c, 0x37, 0xb9, 0xbf, 0x69, 0xda, 0x54, 0x11, 0x38, 0xb5, 0x53,
+            0x4d, 0xd6, 0x1e, 0xc3, 0xe7, 0xc3, 0xf8, 0x67, 0xfb, 0xba, 0x87, 0x7f, 0xa2, 0x4a,
+            0x8b, 0x0d, 0xce, 0xf6,
+        ];
+
+        let (pk, sk) = super::key_pair(&SEED, 5).unwrap();
+        let sig = super::sign(&sk, MSG, CTX, 5).unwrap();
+        assert_eq!(shake256_digest(&pk), pk_digest);
+        assert_eq!(shake256_digest(&sk), sk_digest);
+        assert_eq!(shake256_digest(&sig), sig_digest);
+        assert!(super::verify(&sig, &pk, MSG, CTX));
+    }
+
+    #[test]
+    fn test_ml_dsa_sizes() {
+        for (security_level, pk_len, sk_len, sig_len) in [(2, 1312, 2560, 2420), (3, 1952, 4032, 3309), (5, 2592, 4896, 4627)] {
+            let (pk, sk) = super::key_pair(&SEED, security_level).unwrap();
+            let sig = super::sign(&sk, MSG, CTX, security_level).unwrap();
+            assert_eq!((pk.len(), sk.len(), sig.len()), (pk_len, sk_len, sig_len));
+        }
+    }
+
+    #[test]
+    fn test_ml_dsa_reject() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            let sig = sign(&sk, MSG, CTX, security_level).unwrap();
+            assert!(!verify(&sig, &pk, MSG, b"other context"));
+            assert!(!verify(&sig, &pk, MSG, &[]));
+            assert!(!verify(&sig, &pk, b"other message", CTX));
+            assert!(!verify(&sig, &pk, MSG, &[0u8; 256]));
+
+            // an ML-DSA signature is not a valid dilithium signature and vice versa
+            assert!(!crate::sign::verify(&sig, &pk, MSG));
+            let (pk3, sk3) = crate::sign::key_pair(&SEED, security_level).unwrap();
+            let sig3 = crate::sign::sign(&sk3, MSG, security_level).unwrap();
+            assert!(!verify(&sig3, &pk3, MSG, &[]));
+        }
+    }
+
+    #[test]
+    fn test_sign_errors() {
+        use super::*;
+        let (_, sk) = key_pair(&SEED, 2).unwrap();
+        assert_eq!(sign(&sk, MSG, &[0u8; 256], 2), Err(Error::ContextTooLong(256)));
+        assert_eq!(sign(&sk[..100], MSG, CTX, 2), Err(Error::InvalidLength(100)));
+        assert_eq!(sign(&sk, MSG, CTX, 3), Err(Error::InvalidLength(sk.len())));
+        assert_eq!(sign(&sk, MSG, CTX, 4), Err(Error::UnsupportedLevel(4)));
+        assert_eq!(key_pair(&SEED, 4).unwrap_err(), Error::UnsupportedLevel(4));
+        assert_eq!(crate::sign::key_pair(&SEED, 1).unwrap_err(), Error::UnsupportedLevel(1));
+        assert_eq!(crate::sign::sign(&sk[..100], MSG, 2), Err(Error::InvalidLength(100)));
+        assert_eq!(crate::sign::sign(&sk, MSG, 7), Err(Error::UnsupportedLevel(7)));
+        let mut rng = rand::thread_rng();
+        assert_eq!(sign_randomized(&sk, MSG, &[0u8; 256], 2, &mut rng), Err(Error::ContextTooLong(256)));
+        assert_eq!(sign_with_rnd(&sk[..0], MSG, CTX, &[0u8; 32], 2), Err(Error::InvalidLength(0)));
+    }
+
+    #[test]
+    fn test_typed_api() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let sig = sk.sign(MSG, CTX).unwrap();
+            assert_eq!(sig.to_bytes(), sign(&sk.to_bytes(), MSG, CTX, security_level).unwrap());
+            assert_eq!(vk.verify(MSG, CTX, &sig), Ok(()));
+            assert_eq!(vk.verify(MSG, b"other context", &sig), Err(Error::InvalidSignature));
+            assert_eq!(vk.verify(MSG, &[0u8; 256], &sig), Err(Error::ContextTooLong(256)));
+            assert_eq!(sk.sign(MSG, &[0u8; 256]), Err(Error::ContextTooLong(256)));
+
+            let sk2 = SigningKey::from_bytes(&sk.to_bytes()).unwrap();
+            let vk2 = VerifyingKey::from_bytes(&vk.to_bytes()).unwrap();
+            let sig2 = Signature::from_bytes(&sig.to_bytes()).unwrap();
+            assert_eq!(sk2, sk);
+            assert_eq!((vk2.security_level(), sig2.security_level()), (security_level, security_level));
+            assert_eq!(vk2.verify(MSG, CTX, &sig2), Ok(()));
+        }
+        assert_eq!(generate(&SEED, 4).unwrap_err(), Error::UnsupportedLevel(4));
+
+        // a signature of another parameter set is rejected
+        let (_, vk2) = generate(&SEED, 2).unwrap();
+        let (sk3, _) = generate(&SEED, 3).unwrap();
+        let sig3 = sk3.sign(MSG, CTX).unwrap();
+        assert_eq!(vk2.verify(MSG, CTX, &sig3), Err(Error::InvalidSignature));
+    }
+
+    #[test]
+    fn test_typed_api_malformed() {
+        use super::*;
+        let (sk, vk) = generate(&SEED, 2).unwrap();
+        let sig = sk.sign(MSG, CTX).unwrap();
+        for bytes in [sk.to_bytes().to_vec(), vk.to_bytes(), sig.to_bytes()] {
+            let short = &bytes[..bytes.len() - 1];
+            let err = Error::InvalidLength(bytes.len() - 1);
+            assert_eq!(SigningKey::from_bytes(short).unwrap_err(), err);
+            assert_eq!(VerifyingKey::from_bytes(short).unwrap_err(), err);
+            assert_eq!(Signature::from_bytes(short).unwrap_err(), err);
+        }
+
+        // the first 3 bits of s1 decode to eta - 7
+        let mut bad_sk = sk.to_bytes();
+        bad_sk[128] |= 0x07;
+        assert_eq!(SigningKey::from_bytes(&bad_sk), Err(Error::MalformedEncoding));
+
+        // the hint counts are stored in the last k bytes, they must not exceed omega
+        let mut bad_sig = sig.to_bytes();
+        let n = bad_sig.len();
+        bad_sig[n - 1] = 0xff;
+        assert_eq!(Signature::from_bytes(&bad_sig), Err(Error::MalformedEncoding));
+        bad_sig[n - 1] = 0;
+        bad_sig[n - 4] = 1;
+        assert_eq!(Signature::from_bytes(&bad_sig), Err(Error::MalformedEncoding));
+
+        // random bytes of the right length never panic
+        let mut H = Shake256::default();
+        H.update(b"random signatures");
+        let mut reader = H.finalize_xof();
+        for _ in 0..100 {
+            let mut bytes = vec![0u8; sig.to_bytes().len()];
+            reader.read(&mut bytes);
+            if let Ok(sig) = Signature::from_bytes(&bytes) {
+                assert_eq!(vk.verify(MSG, CTX, &sig), Err(Error::InvalidSignature));
+            }
+            assert!(!verify(&bytes, &vk.to_bytes(), MSG, CTX));
+        }
+    }
+
+    #[test]
+    fn test_verify_detailed() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            let sig = sign(&sk, MSG, CTX, security_level).unwrap();
+            assert_eq!(verify_detailed(&sig, &pk, MSG, CTX), Ok(()));
+            assert_eq!(verify_detailed(&sig, &pk, MSG, b"other context"), Err(VerifyError::ChallengeMismatch));
+            assert_eq!(verify_detailed(&sig, &pk, MSG, &[0u8; 256]), Err(VerifyError::ContextTooLong(256)));
+            assert_eq!(
+                verify_detailed(&sig[..sig.len() - 1], &pk, MSG, CTX),
+                Err(VerifyError::InvalidLength { pk: pk.len(), sig: sig.len() - 1 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        use super::*;
+        // two signers at level 2 and one at level 3 and 5, each signing 6 messages
+        let levels = [2, 3, 5, 2];
+        let keys: Vec<_> = (0..4).map(|i| key_pair(&[i as u8; 32], levels[i]).unwrap()).collect();
+        let msgs: Vec<Vec<u8>> = (0..24u8).map(|i| vec![i; i as usize]).collect();
+        let mut sigs: Vec<Vec<u8>> = Vec::new();
+        for (i, m) in msgs.iter().enumerate() {
+            sigs.push(sign(&keys[i % 4].1, m, CTX, levels[i % 4]).unwrap());
+        }
+        sigs[5][0] ^= 1;
+        sigs[6].pop();
+        let mut items: Vec<(&[u8], &[u8], &[u8])> = Vec::new();
+        for (i, (sig, m)) in sigs.iter().zip(&msgs).enumerate() {
+            items.push((sig, &keys[i % 4].0, m));
+        }
+        // a signature checked against the key of another signer of the same level
+        items[7].1 = &keys[0].0;
+        items[11].1 = &keys[0].0[1..];
+
+        let results = verify_batch(&items, CTX);
+        for (&(sig, pk, m), result) in items.iter().zip(&results) {
+            assert_eq!(*result, verify_detailed(sig, pk, m, CTX));
+        }
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 20);
+        assert_eq!(results[6], Err(VerifyError::InvalidLength { pk: 2592, sig: 4626 }));
+        assert_eq!(verify_batch(&items, &[0u8; 256]), vec![Err(VerifyError::ContextTooLong(256)); 24]);
+        assert!(verify_batch(&[], CTX).is_empty());
+    }
+
+    #[test]
+    fn test_prepared_signing_key() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let prepared = sk.prepare();
+            assert_eq!(prepared.security_level(), security_level);
+            for i in 0..4u8 {
+                let m = [i; 33];
+                let rnd = [i; 32];
+                assert_eq!(prepared.sign(&m, CTX), sk.sign(&m, CTX));
+                assert_eq!(prepared.sign_with_rnd(&m, CTX, &rnd), sk.sign_with_rnd(&m, CTX, &rnd));
+            }
+            assert_eq!(prepared.sign(MSG, &[0u8; 256]), Err(Error::ContextTooLong(256)));
+
+            let mu = vk.compute_mu(MSG, CTX).unwrap();
+            assert_eq!(prepared.sign_external_mu(&mu), sk.sign_external_mu(&mu));
+            let digest = HashAlgorithm::Sha256.digest(MSG);
+            let sig = prepared.hash_sign(&digest, CTX, HashAlgorithm::Sha256).unwrap();
+            assert_eq!(sig, sk.hash_sign(&digest, CTX, HashAlgorithm::Sha256).unwrap());
+            assert_eq!(vk.hash_verify(&digest, CTX, HashAlgorithm::Sha256, &sig), Ok(()));
+
+            let sig = prepared.sign_randomized(MSG, CTX, &mut rand::thread_rng()).unwrap();
+            assert_eq!(vk.verify(MSG, CTX, &sig), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_online_signer() {
+        use super::*;
+        let mut rng = rand::thread_rng();
+        for security_level in [2, 3, 5] {
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let mut signer = OnlineSigner::new(sk.prepare());
+            assert_eq!((signer.security_level(), signer.available()), (security_level, 0));
+
+            // every attempt takes a commitment from the pool, accepted or rejected
+            signer.precompute(200, &mut rng);
+            let mut available = signer.available();
+            for i in 0..8u8 {
+                let sig = signer.sign(&[i; 10], CTX, &mut rng).unwrap();
+                assert_eq!(vk.verify(&[i; 10], CTX, &sig), Ok(()));
+                assert!(signer.available() < available);
+                available = signer.available();
+            }
+
+            // the empty pool falls back to fresh commitments
+            let mut fresh = OnlineSigner::new(sk.prepare());
+            fresh.precompute(1, &mut rng);
+            for i in 0..4u8 {
+                let sig = fresh.sign(&[i; 10], CTX, &mut rng).unwrap();
+                assert_eq!(vk.verify(&[i; 10], CTX, &sig), Ok(()));
+                assert_eq!(fresh.available(), 0);
+            }
+
+            signer.precompute(10, &mut rng);
+            let mu = vk.compute_mu(MSG, CTX).unwrap();
+            assert_eq!(vk.verify_external_mu(&mu, &signer.sign_external_mu(&mu, &mut rng)), Ok(()));
+            let digest = HashAlgorithm::Sha3_256.digest(MSG);
+            let sig = signer.hash_sign(&digest, CTX, HashAlgorithm::Sha3_256, &mut rng).unwrap();
+            assert_eq!(vk.hash_verify(&digest, CTX, HashAlgorithm::Sha3_256, &sig), Ok(()));
+            assert_eq!(signer.sign(MSG, &[0u8; 256], &mut rng), Err(Error::ContextTooLong(256)));
+        }
+    }
+
+    #[test]
+    fn test_prepared_verifying_key() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let prepared = vk.prepare();
+            assert_eq!(prepared.security_level(), security_level);
+            for i in 0..4u8 {
+                let m = [i; 33];
+                let sig = sk.sign(&m, CTX).unwrap();
+                assert_eq!(prepared.verify(&m, CTX, &sig), Ok(()));
+                assert_eq!(prepared.verify(&m, b"other context", &sig), Err(Error::InvalidSignature));
+                assert_eq!(prepared.verify(&[i; 32], CTX, &sig), Err(Error::InvalidSignature));
+                assert_eq!(prepared.compute_mu(&m, CTX), vk.compute_mu(&m, CTX));
+            }
+            let sig = sk.sign(MSG, CTX).unwrap();
+            assert_eq!(prepared.verify(MSG, &[0u8; 256], &sig), Err(Error::ContextTooLong(256)));
+
+            let mu = vk.compute_mu(MSG, CTX).unwrap();
+            assert_eq!(prepared.verify_external_mu(&mu, &sk.sign_external_mu(&mu)), Ok(()));
+            let digest = HashAlgorithm::Sha512.digest(MSG);
+            let sig = sk.hash_sign(&digest, CTX, HashAlgorithm::Sha512).unwrap();
+            assert_eq!(prepared.hash_verify(&digest, CTX, HashAlgorithm::Sha512, &sig), Ok(()));
+            assert_eq!(
+                prepared.hash_verify(&digest, CTX, HashAlgorithm::Sha3_512, &sig),
+                Err(Error::InvalidSignature)
+            );
+        }
+
+        // a signature of another parameter set is rejected
+        let (_, vk2) = generate(&SEED, 2).unwrap();
+        let (sk3, _) = generate(&SEED, 3).unwrap();
+        let sig3 = sk3.sign(MSG, CTX).unwrap();
+        assert_eq!(vk2.prepare().verify(MSG, CTX, &sig3), Err(Error::InvalidSignature));
+    }
+
+    #[test]
+    fn test_sign_with_rnd() {
+        // SHAKE256 digests of the hedged signatures produced by the FIPS 204 reference implementation
+        // with rnd = 0x20, 0x21, ..., 0x3f
+        let sig_digests = [
+            [
+                0xe1, 0xcc, 0x0c, 0x2c, 0xd4, 0x34, 0x54, 0x63, 0x97, 0xe5, 0xd4, 0x25, 0xb2, 0x14,
+                0xf1, 0x81, 0x22, 0x6a, 0xac, 0x5a, 0x12, 0xa5, 0xfa, 0x7e, 0x26, 0x6b, 0x71, 0x5f,
+                0xd9, 0xfb, 0xe5, 0x9a,
+            ],
+            [
+                0x40, 0xea, 0xa7, 0xf4, 0x3b, 0xf5, 0xbf, 0xbb, 0xf8, 0x94, 0xba, 0x3a, 0x02, 0x54,
+                0x5b, 0xf4, 0x1c, 0x6d, 0x8e, 0x4e, 0x12, 0x79, 0x70, 0x48, 0x50, 0xe1, 0x03, 0x78,
+                0x22, 0xaf, 0x86, 0x82,
+            ],
+            [
+                0x8d, 0xfd, 0x48, 0xbc, 0xee, 0xb8, 0xd9, 0xe4, 0x84, 0x41, 0xab, 0x55, 0xd6, 0x19,
+                0x71, 0xf0, 0xc6, 0x49, 0x8d, 0x25, 0x9c, 0x09, 0x7c, 0x66, 0xa6, 0x4b, 0xb8, 0xe3,
+                0x99, 0xc2, 0x4b, 0x60,
+            ],
+        ];
+        let mut rnd = [0u8; 32];
+        for (i, b) in rnd.iter_mut().enumerate() {
+            *b = 0x20 + i as u8;
+        }
+
+        for (security_level, sig_digest) in [2, 3, 5].into_iter().zip(sig_digests) {
+            let (pk, sk) = super::key_pair(&SEED, security_level).unwrap();
+            let sig = super::sign_with_rnd(&sk, MSG, CTX, &rnd, security_level).unwrap();
+            assert_eq!(shake256_digest(&sig), sig_digest);
+            assert!(super::verify(&sig, &pk, MSG, CTX));
+        }
+    }
+
+    #[test]
+    fn test_sign_randomized() {
+        use super::*;
+        use rand::{rngs::StdRng, RngCore, SeedableRng};
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            let mut rng = rand::thread_rng();
+            let sig1 = sign_randomized(&sk, MSG, CTX, security_level, &mut rng).unwrap();
+            let sig2 = sign_randomized(&sk, MSG, CTX, security_level, &mut rng).unwrap();
+            assert_ne!(sig1, sig2);
+            assert!(verify(&sig1, &pk, MSG, CTX));
+            assert!(verify(&sig2, &pk, MSG, CTX));
+
+            // an injected rng gives the same signature as passing its output as rnd
+            let mut rnd = [0u8; 32];
+            StdRng::seed_from_u64(7).fill_bytes(&mut rnd);
+            let sig = sign_randomized(&sk, MSG, CTX, security_level, &mut StdRng::seed_from_u64(7)).unwrap();
+            assert_eq!(sig, sign_with_rnd(&sk, MSG, CTX, &rnd, security_level).unwrap());
+
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let sig = sk.sign_randomized(MSG, CTX, &mut StdRng::seed_from_u64(7)).unwrap();
+            assert_eq!(sig, sk.sign_with_rnd(MSG, CTX, &rnd).unwrap());
+            assert_eq!(vk.verify(MSG, CTX, &sig), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        use super::*;
+        use rand::{rngs::StdRng, SeedableRng};
+        // a message that is not a multiple of the SHAKE256 rate of 136 bytes
+        let msg: Vec<u8> = (0..10000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for security_level in [2, 3, 5] {
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let sig = sk.sign(&msg, CTX).unwrap();
+
+            let mut signer = sk.signer(CTX).unwrap();
+            for chunk in msg.chunks(1000) {
+                signer.update(chunk);
+            }
+            assert_eq!(signer.finalize(), sig);
+
+            let mut signer = sk.signer(CTX).unwrap();
+            assert_eq!(signer.update_reader(&mut &msg[..]).unwrap(), msg.len() as u64);
+            assert_eq!(signer.finalize(), sig);
+
+            let mut signer = sk.signer(CTX).unwrap();
+            signer.update(&msg);
+            let sig2 = signer.finalize_randomized(&mut StdRng::seed_from_u64(7));
+            assert_eq!(vk.verify(&msg, CTX, &sig2), Ok(()));
+
+            let mut verifier = vk.verifier(CTX).unwrap();
+            for chunk in msg.chunks(333) {
+                verifier.update(chunk);
+            }
+            assert_eq!(verifier.finalize(&sig), Ok(()));
+
+            let mut verifier = vk.verifier(CTX).unwrap();
+            verifier.update_reader(&mut &msg[..msg.len() - 1]).unwrap();
+            assert_eq!(verifier.finalize(&sig), Err(Error::InvalidSignature));
+
+            let mut verifier = vk.verifier(b"other context").unwrap();
+            verifier.update(&msg);
+            assert_eq!(verifier.finalize(&sig), Err(Error::InvalidSignature));
+        }
+        let (sk, vk) = generate(&SEED, 2).unwrap();
+        assert_eq!(sk.signer(&[0u8; 256]).err(), Some(Error::ContextTooLong(256)));
+        assert_eq!(vk.verifier(&[0u8; 256]).err(), Some(Error::ContextTooLong(256)));
+    }
+
+    #[test]
+    fn test_hash_ml_dsa() {
+        use super::*;
+        // SHAKE256 digests of the HashML-DSA signatures for levels 2, 3 and 5 produced by the FIPS 204 reference
+        // implementation
+        let sig_digests = [
+            (
+                HashAlgorithm::Sha256,
+                [
+                    [
+                        0x8a, 0x39, 0x9a, 0x06, 0x36, 0x81, 0xc2, 0x2a, 0xf0, 0x9d, 0xd6, 0x2a, 0xcc, 0x46,
+                        0x52, 0xf5, 0xa2, 0x09, 0x31, 0xe6, 0x88, 0x7e, 0x3f, 0xed, 0xf4, 0xb0, 0xd0, 0xf1,
+                        0xfd, 0x01, 0x30, 0xde,
+                    ],
+                    [
+                        0xa1, 0xa7, 0x76, 0xf6, 0x03, 0x0d, 0x72, 0x4f, 0x14, 0x93, 0x03, 0x57, 0x92, 0x34,
+                        0x0d, 0x7f, 0x8a, 0x08, 0x78, 0xb3, 0x88, 0xa0, 0x4d, 0x65, 0x7f, 0x4f, 0xa0, 0x0d,
+                        0x4d, 0x3f, 0x96, 0x70,
+                    ],
+                    [
+                        0x0e, 0x03, 0xba, 0xd8, 0x38, 0x13, 0xf9, 0xb8, 0x61, 0x8c, 0x55, 0x2b, 0x72, 0x63,
+                        0x1e, 0xde, 0x8a, 0x77, 0x0f, 0x80, 0x65, 0xaa, 0x78, 0xc3, 0x31, 0x5d, 0x7d, 0x77,
+                        0x97, 0xff, 0x67, 0xb4,
+                    ],
+                ],
+            ),
+            (
+                HashAlgorithm::Sha512,
+                [
+                    [
+                        0x60, 0x64, 0x16, 0xde, 0x65, 0x8b, 0x0e, 0xf3, 0x40, 0x97, 0xdd, 0x5f, 0x78, 0x57,
+                        0xae, 0x55, 0x9d, 0x04, 0x51, 0xce, 0xde, 0xc0, 0xe4, 0x47, 0xa1, 0xd0, 0x16, 0x94,
+                        0x20, 0xed, 0x9d, 0x78,
+                    ],
+                    [
+                        0xf2, 0xea, 0xd2, 0xfb, 0x23, 0x0f, 0xa6, 0x72, 0x88, 0xe9, 0xd3, 0x34, 0x0f, 0x15,
+                        0xc5, 0x7c, 0x9e, 0x8b, 0x7b, 0xdb, 0xe9, 0x00, 0x89, 0xc2, 0x25, 0x76, 0x23, 0x0c,
+                        0xfd, 0x9c, 0xd2, 0xcc,
+                    ],
+                    [
+                        0xc8, 0xd0, 0xb4, 0x35, 0x51, 0xa8, 0x24, 0x82, 0xc2, 0xb2, 0x1f, 0x97, 0x72, 0x81,
+                        0x96, 0xf1, 0x85, 0x01, 0x36, 0xe7, 0x16, 0xf0, 0x5d, 0xa1, 0x07, 0x53, 0x63, 0x41,
+                        0xc5, 0x07, 0x6a, 0x4e,
+                    ],
+                ],
+            ),
+            (
+                HashAlgorithm::Sha3_256,
+                [
+                    [
+                        0x2f, 0xd0, 0x66, 0xd5, 0x4e, 0xd5, 0x71, 0xbb, 0x1a, 0xfd, 0xfd, 0x67, 0xcc, 0x76,
+                        0xdd, 0xdd, 0xfe, 0x2e, 0xf6, 0xc9, 0x60, 0x04, 0xce, 0x97, 0x9b, 0x71, 0x60, 0x83,
+                        0x8c, 0xaa, 0x29, 0x9b,
+                    ],
+                    [
+                        0x5c, 0xf2, 0xcd, 0xa6, 0x11, 0x8f, 0x28, 0xa3, 0x10, 0xb8, 0x5b, 0xac, 0x18, 0x92,
+                        0xa0, 0xc5, 0x63, 0xdf, 0x77, 0xc9, 0x9e, 0xf4, 0x1e, 0x7b, 0x08, 0x15, 0x7c, 0x64,
+                        0x94, 0x74, 0x49, 0xb2,
+                    ],
+                    [
+                        0xea, 0xbd, 0x32, 0x11, 0x8c, 0x95, 0x33, 0x8c, 0xd1, 0xaa, 0x61, 0xf6, 0x83, 0xc6,
+                        0xa1, 0x93, 0x67, 0x00, 0xb7, 0x75, 0x41, 0x52, 0xdf, 0x18, 0x32, 0xc6, 0x0e, 0x23,
+                        0x25, 0x43, 0x31, 0xb3,
+                    ],
+                ],
+            ),
+            (
+                HashAlgorithm::Sha3_512,
+                [
+                    [
+                        0x34, 0x3a, 0x36, 0xf7, 0x9c, 0xd2, 0x81, 0xd9, 0x99, 0xbf, 0xd6, 0x0d, 0xcc, 0xcc,
+                        0xdd, 0x43, 0x8a, 0xe1, 0x40, 0x93, 0x5a, 0x6e, 0x97, 0x3e, 0x3a, 0xf9, 0x0c, 0x9d,
+                        0x4a, 0x34, 0x12, 0xb8,
+                    ],
+                    [
+                        0xb5, 0x61, 0xbc, 0x7a, 0xc9, 0x61, 0x4b, 0x52, 0x31, 0xb4, 0x3d, 0x1f, 0x5f, 0x8b,
+                        0xe3, 0xbc, 0xf5, 0x77, 0xc0, 0x3f, 0xd2, 0x3f, 0x76, 0x00, 0xd7, 0xf6, 0x8e, 0x4e,
+                        0x24, 0x04, 0x60, 0x19,
+                    ],
+                    [
+                        0xb7, 0xc7, 0x92, 0x55, 0x9f, 0x7b, 0x04, 0xbd, 0xb8, 0x15, 0x00, 0xc0, 0x17, 0x73,
+                        0xd9, 0x4d, 0xdc, 0xed, 0xff, 0x2f, 0x02, 0x45, 0xf7, 0xdf, 0x3f, 0x0a, 0x42, 0x9a,
+                        0xda, 0xb2, 0x6e, 0xee,
+                    ],
+                ],
+            ),
+            (
+                HashAlgorithm::Shake128,
+                [
+                    [
+                        0x49, 0xb7, 0x4c, 0xba, 0xe5, 0xb9, 0x6c, 0x48, 0x9d, 0xd5, 0xab, 0xb0, 0xd5, 0xa0,
+                        0x19, 0x3d, 0x6f, 0x2a, 0x97, 0x24, 0xad, 0x63, 0x46, 0x28, 0xb8, 0x42, 0x76, 0x0e,
+                        0x9d, 0x99, 0xab, 0xd0,
+                    ],
+                    [
+                        0xda, 0xe3, 0x34, 0x48, 0xd7, 0xb9, 0x30, 0x6f, 0x45, 0xee, 0xdc, 0x40, 0xb9, 0x29,
+                        0x5e, 0x7a, 0x8b, 0xa2, 0xa7, 0x1f, 0xdb, 0x85, 0xec, 0xd7, 0x8a, 0x2b, 0x42, 0xbf,
+                        0xa4, 0xe0, 0x9c, 0x9f,
+                    ],
+                    [
+                        0xc3, 0x70, 0x53, 0x5f, 0xf6, 0xc1, 0xa1, 0x8d, 0xdf, 0x6e, 0x87, 0x30, 0x4f, 0x7e,
+                        0x2c, 0x38, 0x8a, 0xc9, 0x40, 0x28, 0x7d, 0x63, 0x1a, 0x3a, 0xa7, 0x55, 0x97, 0xf5,
+                        0xec, 0x74, 0x62, 0xe0,
+                    ],
+                ],
+            ),
+            (
+                HashAlgorithm::Shake256,
+                [
+                    [
+                        0x62, 0x20, 0x66, 0x6a, 0x01, 0x84, 0xd5, 0x5a, 0xf2, 0xa7, 0x07, 0x79, 0x83, 0xcf,
+                        0xb6, 0x57, 0xb3, 0xb3, 0x0a, 0xe2, 0x46, 0x5a, 0x70, 0x68, 0x3b, 0x94, 0xf2, 0x01,
+                        0xf9, 0xfc, 0xe5, 0xbc,
+                    ],
+                    [
+                        0xbf, 0x8d, 0x00, 0xd5, 0x00, 0x6c, 0x1b, 0x92, 0xd8, 0xe4, 0xba, 0x26, 0x83, 0x2d,
+                        0xbd, 0x0b, 0xfe, 0xaf, 0x5a, 0x80, 0xde, 0x90, 0x90, 0xe9, 0xeb, 0x24, 0x4d, 0x93,
+                        0xfa, 0xc8, 0x61, 0xb6,
+                    ],
+                    [
+                        0xf2, 0x75, 0x84, 0xff, 0xf4, 0x9b, 0x40, 0xa9, 0x1c, 0x99, 0x15, 0x76, 0xf0, 0xbb,
+                        0xc2, 0x3b, 0x18, 0x96, 0xf2, 0xfe, 0xea, 0x8f, 0x24, 0x47, 0xa6, 0xc4, 0xfc, 0x76,
+                        0x33, 0xc1, 0x57, 0x7a,
+                    ],
+                ],
+            ),
+        ];
+
+        for (ph, digests) in sig_digests {
+            let digest = ph.digest(MSG);
+            assert_eq!(digest.len(), ph.digest_len());
+            for (security_level, sig_digest) in [2, 3, 5].into_iter().zip(digests) {
+                let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+                let sig = hash_sign(&sk, &digest, CTX, ph, security_level).unwrap();
+                assert_eq!(shake256_digest(&sig), sig_digest);
+                assert!(hash_verify(&sig, &pk, &digest, CTX, ph));
+
+                // the pre-hash is bound to the signature, as is the domain separation from pure ML-DSA
+                assert!(!verify(&sig, &pk, &digest, CTX));
+                let other = if ph == HashAlgorithm::Sha256 { HashAlgorithm::Sha3_256 } else { HashAlgorithm::Sha256 };
+                assert!(!hash_verify(&sig, &pk, &other.digest(MSG), CTX, other));
+                assert!(!hash_verify(&sig, &pk, &ph.digest(b"other message"), CTX, ph));
+            }
+        }
+
+        // a digest of the wrong length, a long ctx or a short sk are errors
+        let (_, sk) = key_pair(&SEED, 2).unwrap();
+        let digest = HashAlgorithm::Sha256.digest(MSG);
+        assert_eq!(hash_sign(&sk, &digest[..5], CTX, HashAlgorithm::Sha256, 2), Err(Error::InvalidDigestLength(5)));
+        assert_eq!(hash_sign(&sk, &digest, &[0u8; 256], HashAlgorithm::Sha256, 2), Err(Error::ContextTooLong(256)));
+        assert_eq!(hash_sign(&sk[..10], &digest, CTX, HashAlgorithm::Sha256, 2), Err(Error::InvalidLength(10)));
+        let mut rng = rand::thread_rng();
+        assert_eq!(
+            hash_sign_randomized(&sk, &digest[..5], CTX, HashAlgorithm::Sha256, 2, &mut rng),
+            Err(Error::InvalidDigestLength(5))
+        );
+    }
+
+    #[test]
+    fn test_hash_ml_dsa_typed() {
+        use super::*;
+        use rand::{rngs::StdRng, SeedableRng};
+        let (sk, vk) = generate(&SEED, 3).unwrap();
+        let ph = HashAlgorithm::Sha512;
+        let digest = ph.digest(MSG);
+        let sig = sk.hash_sign(&digest, CTX, ph).unwrap();
+        assert_eq!(sig.to_bytes(), hash_sign(&sk.to_bytes(), &digest, CTX, ph, 3).unwrap());
+        assert_eq!(vk.hash_verify(&digest, CTX, ph, &sig), Ok(()));
+        assert_eq!(vk.hash_verify(&digest, b"", ph, &sig), Err(Error::InvalidSignature));
+
+        let sig = sk.hash_sign_randomized(&digest, CTX, ph, &mut StdRng::seed_from_u64(7)).unwrap();
+        assert_eq!(vk.hash_verify(&digest, CTX, ph, &sig), Ok(()));
+
+        assert_eq!(sk.hash_sign(&digest[..32], CTX, ph), Err(Error::InvalidDigestLength(32)));
+        assert_eq!(vk.hash_verify(&digest[..32], CTX, ph, &sig), Err(Error::InvalidDigestLength(32)));
+        assert_eq!(sk.hash_sign(&digest, &[0u8; 256], ph), Err(Error::ContextTooLong(256)));
+    }
+
+    #[test]
+    fn test_external_mu() {
+        use super::*;
+        use rand::{rngs::StdRng, RngCore, SeedableRng};
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            let mu = compute_mu(&pk, MSG, CTX).unwrap();
+            let sig = sign_external_mu(&sk, &mu, security_level).unwrap();
+            assert_eq!(sig, sign(&sk, MSG, CTX, security_level).unwrap());
+            assert!(verify(&sig, &pk, MSG, CTX));
+            assert!(verify_external_mu(&sig, &pk, &mu));
+            assert!(!verify_external_mu(&sig, &pk, &compute_mu(&pk, MSG, b"other context").unwrap()));
+            assert_eq!(compute_mu(&pk, MSG, &[0u8; 256]), Err(Error::ContextTooLong(256)));
+            assert_eq!(sign_external_mu(&sk[1..], &mu, security_level), Err(Error::InvalidLength(sk.len() - 1)));
+            assert_eq!(sign_external_mu(&sk, &mu, 4), Err(Error::UnsupportedLevel(4)));
+
+            let mut rnd = [0u8; 32];
+            StdRng::seed_from_u64(7).fill_bytes(&mut rnd);
+            let sig = sign_external_mu_randomized(&sk, &mu, security_level, &mut StdRng::seed_from_u64(7)).unwrap();
+            assert_eq!(sig, sign_with_rnd(&sk, MSG, CTX, &rnd, security_level).unwrap());
+
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            let mu = vk.compute_mu(MSG, CTX).unwrap();
+            let sig = sk.sign_external_mu(&mu);
+            assert_eq!(sig, sk.sign(MSG, CTX).unwrap());
+            assert_eq!(vk.verify_external_mu(&mu, &sig), Ok(()));
+            let sig = sk.sign_external_mu_randomized(&mu, &mut StdRng::seed_from_u64(7));
+            assert_eq!(vk.verify(MSG, CTX, &sig), Ok(()));
+            assert_eq!(vk.compute_mu(MSG, &[0u8; 256]), Err(Error::ContextTooLong(256)));
+        }
+    }
+
+    #[test]
+    fn test_seed_only_key() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (sk, _) = generate(&SEED, security_level).unwrap();
+            assert_eq!(sk.seed().as_deref(), Some(&SEED));
+
+            let sk2 = SigningKey::from_seed(&SEED, security_level).unwrap();
+            assert_eq!(sk2, sk);
+            assert_eq!(sk2.seed().as_deref(), Some(&SEED));
+            assert_eq!(sk2.sign(MSG, CTX), sk.sign(MSG, CTX));
+
+            // the expanded form alone has no seed, but is the same key
+            let sk3 = SigningKey::from_bytes(&sk.to_bytes()).unwrap();
+            assert_eq!(sk3.seed(), None);
+            assert_eq!(sk3, sk);
+
+            let sk4 = SigningKey::from_seed_and_bytes(&SEED, &sk.to_bytes()).unwrap();
+            assert_eq!((sk4.seed().as_deref(), sk4.security_level()), (Some(&SEED), security_level));
+
+            let mut other_seed = SEED;
+            other_seed[0] ^= 1;
+            assert_eq!(
+                SigningKey::from_seed_and_bytes(&other_seed, &sk.to_bytes()),
+                Err(Error::InconsistentKey)
+            );
+        }
+        assert_eq!(SigningKey::from_seed(&SEED, 1), Err(Error::UnsupportedLevel(1)));
+        assert_eq!(SigningKey::from_seed_and_bytes(&SEED, &[0u8; 100]), Err(Error::InvalidLength(100)));
+    }
+
+    #[test]
+    fn test_verifying_key_from_signing_key() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (sk, vk) = generate(&SEED, security_level).unwrap();
+            assert_eq!(sk.verifying_key(), Ok(vk.clone()));
+            assert_eq!(public_key(&sk.to_bytes()), Ok(vk.to_bytes()));
+
+            // tr has 64 bytes in ML-DSA
+            let mut bad_sk = sk.to_bytes();
+            bad_sk[100] ^= 1;
+            let bad_sk = SigningKey::from_bytes(&bad_sk).unwrap();
+            assert_eq!(bad_sk.verifying_key(), Err(Error::TrMismatch));
+
+            let mut bad_sk = sk.to_bytes();
+            let n = bad_sk.len();
+            bad_sk[n - 1] ^= 1;
+            assert_eq!(public_key(&bad_sk), Err(Error::T0Mismatch));
+        }
+        // the lengths of round 3 keys are rejected
+        let (_, sk) = crate::sign::key_pair(&SEED, 2).unwrap();
+        assert_eq!(public_key(&sk), Err(Error::InvalidLength(2528)));
+    }
+}
@@ -1,30 +1,47 @@
-use std::vec;
-
+use crate::error::Error;
 use crate::params::d;
 use crate::poly::Poly;
 use crate::polyvec::polyvec::PolyVec;
+use zeroize::Zeroize;
+
+// the packing functions write into caller provided buffers of the exact encoded size, so that they work without alloc
+// a writer appends to such a buffer
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, b: u8) {
+        self.buf[self.pos] = b;
+        self.pos += 1;
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+}
 
 // pack the polyvec t1(coeffs is 10-bits) into byte arrays
 // every 4 coeffs will be packed into 5 bytes
-pub fn pack_pk(t1: &PolyVec, rho: &[u8; 32]) -> Vec<u8> {
-    let mut pk = Vec::new();
-    pk.append(&mut Vec::from(*rho));
-    pk.append(&mut pack_t1(t1));
-    pk
+pub fn pack_pk<const K: usize>(t1: &PolyVec<K>, rho: &[u8; 32], pk: &mut [u8]) {
+    pk[..32].copy_from_slice(rho);
+    pack_t1(t1, &mut pk[32..]);
 }
 
 // unpack pk into t1 and rho
 // l = 4/5/7
 // unpack 5 bytes into 4 coeffs, 320 bytes into 256 coeffs(1 poly), total k polys
-pub fn unpack_pk(pk: &Vec<u8>) -> ([u8; 32], Vec<u8>) {
+pub fn unpack_pk(pk: &[u8]) -> ([u8; 32], &[u8]) {
     let rho = pk[0..32].try_into().unwrap();
-    let t1 = pk[32..].to_vec();
+    let t1 = &pk[32..];
 
     (rho, t1)
 }
 
-pub fn pack_t1(t1: &PolyVec) -> Vec<u8> {
-    let mut buf = Vec::new();
+pub fn pack_t1<const K: usize>(t1: &PolyVec<K>, buf: &mut [u8]) {
+    let mut buf = Writer { buf, pos: 0 };
     for i in 0..t1.vec.len() {
         let mut j = 0;
         loop {
@@ -49,69 +66,69 @@ pub fn pack_t1(t1: &PolyVec) -> Vec<u8> {
             }
         }
     }
-    buf
 }
 
-// every 5 bytes will be unpacked into 4 coeffs
-pub fn unpack_t1(t1_ba: &Vec<u8>, k: i32) -> PolyVec {
-    let mut t1 = PolyVec::new(k as usize);
-    for i in 0..k as usize {
-        let mut j = 0;
-        loop {
-            t1.vec[i].coeffs[j * 4] = (t1_ba[i * 320 + j * 5] as i32)
-                | ((t1_ba[i * 320 + j * 5 + 1] as u16 & 0x03) << 8) as i32; // 8 2
-            t1.vec[i].coeffs[j * 4 + 1] = ((t1_ba[i * 320 + j * 5 + 1] >> 2) & 0x3F) as i32
-                | ((t1_ba[i * 320 + j * 5 + 2] as u16 & 0x0F) << 6) as i32; // 6 4
-            t1.vec[i].coeffs[j * 4 + 2] = ((t1_ba[i * 320 + j * 5 + 2] >> 4) & 0x0F) as i32
-                | ((t1_ba[i * 320 + j * 5 + 3] as u16 & 0x3F) << 4) as i32; // 4 6
-            t1.vec[i].coeffs[j * 4 + 3] = ((t1_ba[i * 320 + j * 5 + 3] >> 6) & 0x03) as i32
-                | ((t1_ba[i * 320 + j * 5 + 4] as u16) << 2) as i32; // 2 8
-            j += 1;
-            if j * 4 == 256 {
-                break;
-            }
+// every 5 bytes will be unpacked into 4 coeffs, t1_ba holds the 320 bytes of one polynomial
+pub fn unpack_t1_poly(t1_ba: &[u8], t1: &mut Poly) {
+    let mut j = 0;
+    loop {
+        t1.coeffs[j * 4] = (t1_ba[j * 5] as i32)
+            | ((t1_ba[j * 5 + 1] as u16 & 0x03) << 8) as i32; // 8 2
+        t1.coeffs[j * 4 + 1] = ((t1_ba[j * 5 + 1] >> 2) & 0x3F) as i32
+            | ((t1_ba[j * 5 + 2] as u16 & 0x0F) << 6) as i32; // 6 4
+        t1.coeffs[j * 4 + 2] = ((t1_ba[j * 5 + 2] >> 4) & 0x0F) as i32
+            | ((t1_ba[j * 5 + 3] as u16 & 0x3F) << 4) as i32; // 4 6
+        t1.coeffs[j * 4 + 3] = ((t1_ba[j * 5 + 3] >> 6) & 0x03) as i32
+            | ((t1_ba[j * 5 + 4] as u16) << 2) as i32; // 2 8
+        j += 1;
+        if j * 4 == 256 {
+            break;
         }
     }
-    t1
 }
 
-pub fn pack_sk(
+// sk is the caller buffer the parts are packed into
+#[allow(clippy::too_many_arguments)]
+pub fn pack_sk<const K: usize, const L: usize>(
     rho: &[u8; 32],
-    K: &[u8; 32],
-    tr: &[u8; 32],
-    s1: &PolyVec,
-    s2: &PolyVec,
-    t0: &PolyVec,
+    key: &[u8; 32],
+    tr: &[u8],
+    s1: &PolyVec<L>,
+    s2: &PolyVec<K>,
+    t0: &PolyVec<K>,
     eta: i32,
-) -> Vec<u8> {
-    let mut sk = Vec::new();
-    sk.append(&mut Vec::from(*rho));
-    sk.append(&mut Vec::from(*K));
-    sk.append(&mut Vec::from(*tr));
-    sk.append(&mut pack_eta(eta, s1));
-    sk.append(&mut pack_eta(eta, s2));
-    sk.append(&mut pack_t0(t0));
-    sk
+    sk: &mut [u8],
+) {
+    let mut sk = Writer { buf: sk, pos: 0 };
+    sk.extend_from_slice(rho);
+    sk.extend_from_slice(key);
+    sk.extend_from_slice(tr);
+    pack_eta(eta, s1, &mut sk);
+    pack_eta(eta, s2, &mut sk);
+    pack_t0(t0, &mut sk);
+    debug_assert_eq!(sk.pos, sk.buf.len());
 }
 
-pub fn unpack_sk(
-    sk: &Vec<u8>,
+// tr has 32 bytes in dilithium and 64 bytes in ML-DSA
+#[cfg(feature = "alloc")]
+pub fn unpack_sk<const K: usize, const L: usize>(
+    sk: &[u8],
     eta: i32,
-    k: i32,
-    l: i32,
-) -> ([u8; 32], [u8; 32], [u8; 32], PolyVec, PolyVec, PolyVec) {
+    trbytes: usize,
+) -> ([u8; 32], [u8; 32], &[u8], PolyVec<L>, PolyVec<K>, PolyVec<K>) {
     let rho = sk[0..32].try_into().unwrap();
-    let K = sk[32..64].try_into().unwrap();
-    let tr = sk[64..96].try_into().unwrap();
-    let (s1, s2) = unpack_eta(eta, k, l, &sk[96..sk.len() - 416 * k as usize].to_vec());
-    let t0 = unpack_t0(k, &sk[sk.len() - 416 * k as usize..].to_vec());
-    (rho, K, tr, s1, s2, t0)
+    let key = sk[32..64].try_into().unwrap();
+    let tr = &sk[64..64 + trbytes];
+    let polyeta = if eta == 2 { 96 } else { 128 };
+    let ba = &sk[64 + trbytes..];
+    let s1 = unpack_eta(eta, &ba[..polyeta * L]);
+    let s2 = unpack_eta(eta, &ba[polyeta * L..polyeta * (K + L)]);
+    let t0 = unpack_t0(&ba[polyeta * (K + L)..]);
+    (rho, key, tr, s1, s2, t0)
 }
 
-// pack s1 and s2 into byte arrays for different eta
-fn pack_eta(eta: i32, t: &PolyVec) -> Vec<u8> {
-    let mut buf = Vec::new();
-
+// pack s1 and s2 into byte arrays for different eta, appended to buf
+fn pack_eta<const N: usize>(eta: i32, t: &PolyVec<N>, buf: &mut Writer) {
     for i in 0..t.vec.len() {
         let mut j: usize = 0;
         loop {
@@ -127,7 +144,7 @@ fn pack_eta(eta: i32, t: &PolyVec) -> Vec<u8> {
                 a[5] = eta - t.vec[i].coeffs[j + 5];
                 a[6] = eta - t.vec[i].coeffs[j + 6];
                 a[7] = eta - t.vec[i].coeffs[j + 7];
-                buf.push((a[0] >> 0) as u8 | (a[1] << 3) as u8 | (a[2] << 6) as u8); // 3 3 2
+                buf.push(a[0] as u8 | (a[1] << 3) as u8 | (a[2] << 6) as u8); // 3 3 2
                 buf.push(
                     (a[2] >> 2) as u8 | (a[3] << 1) as u8 | (a[4] << 4) as u8 | (a[5] << 7) as u8,
                 ); // 1 3 3 1
@@ -148,127 +165,70 @@ fn pack_eta(eta: i32, t: &PolyVec) -> Vec<u8> {
             }
         }
     }
-
-    buf
 }
 
-// unpack s1 and s2 from byte arrays for different eta
-// eta = 2: 3 bytes into 8 coeffs, 96 bytes into 256 coeffs(1 poly), s1 l polys, s2 k polys
-fn unpack_eta(eta: i32, k: i32, l: i32, ba: &Vec<u8>) -> (PolyVec, PolyVec) {
-    // let l = match k {
-    //     4 => 4,
-    //     6 => 5,
-    //     8 => 7,
-    // };
-    let mut s1 = PolyVec::new(l as usize);
-    let mut s2 = PolyVec::new(k as usize);
-
-    for i in 0..l as usize {
-        let mut j = 0;
-        loop {
-            if eta == 2 {
-                s1.vec[i].coeffs[j * 8] = (ba[i * 96 + j * 3] & 0x07) as i32; // 3
-                s1.vec[i].coeffs[j * 8 + 1] = ((ba[i * 96 + j * 3] >> 3) & 0x07) as i32; // 3
-                s1.vec[i].coeffs[j * 8 + 2] = ((ba[i * 96 + j * 3] >> 6) & 0x03) as i32
-                    | ((ba[i * 96 + j * 3 + 1] & 0x01) << 2) as i32; // 2 1
-                s1.vec[i].coeffs[j * 8 + 3] = ((ba[i * 96 + j * 3 + 1] >> 1) & 0x07) as i32; // 3
-                s1.vec[i].coeffs[j * 8 + 4] = ((ba[i * 96 + j * 3 + 1] >> 4) & 0x07) as i32; // 3
-                s1.vec[i].coeffs[j * 8 + 5] = ((ba[i * 96 + j * 3 + 1] >> 7) & 0x01) as i32
-                    | ((ba[i * 96 + j * 3 + 2] & 0x03) << 1) as i32; // 1 2
-                s1.vec[i].coeffs[j * 8 + 6] = ((ba[i * 96 + j * 3 + 2] >> 2) & 0x07) as i32; // 3
-                s1.vec[i].coeffs[j * 8 + 7] = ((ba[i * 96 + j * 3 + 2] >> 5) & 0x07) as i32; // 3
-
-                s1.vec[i].coeffs[j * 8] = eta - s1.vec[i].coeffs[j * 8];
-                s1.vec[i].coeffs[j * 8 + 1] = eta - s1.vec[i].coeffs[j * 8 + 1];
-                s1.vec[i].coeffs[j * 8 + 2] = eta - s1.vec[i].coeffs[j * 8 + 2];
-                s1.vec[i].coeffs[j * 8 + 3] = eta - s1.vec[i].coeffs[j * 8 + 3];
-                s1.vec[i].coeffs[j * 8 + 4] = eta - s1.vec[i].coeffs[j * 8 + 4];
-                s1.vec[i].coeffs[j * 8 + 5] = eta - s1.vec[i].coeffs[j * 8 + 5];
-                s1.vec[i].coeffs[j * 8 + 6] = eta - s1.vec[i].coeffs[j * 8 + 6];
-                s1.vec[i].coeffs[j * 8 + 7] = eta - s1.vec[i].coeffs[j * 8 + 7];
-
-                j += 1;
-                if j * 8 == 256 {
-                    break;
-                }
-            } else if eta == 4 {
-                s1.vec[i].coeffs[j * 2] = (ba[i * 32 + j * 1] & 0x0F) as i32; // 4
-                s1.vec[i].coeffs[j * 2 + 1] = ((ba[i * 32 + j * 1] >> 4) & 0x0F) as i32; // 4
-
-                s1.vec[i].coeffs[j * 2] = eta - s1.vec[i].coeffs[j * 2];
-                s1.vec[i].coeffs[j * 2 + 1] = eta - s1.vec[i].coeffs[j * 2 + 1];
-
-                j += 1;
-                if j * 2 == 256 {
-                    break;
-                }
-            } else {
-                panic!("eta should be 2 or 4");
-            }
-        }
+// unpack s1 or s2 from byte arrays for different eta
+// eta = 2: 3 bytes into 8 coeffs, 96 bytes into 256 coeffs(1 poly)
+// eta = 4: 1 byte into 2 coeffs, 128 bytes into 256 coeffs(1 poly)
+#[cfg(feature = "alloc")]
+fn unpack_eta<const N: usize>(eta: i32, ba: &[u8]) -> PolyVec<N> {
+    let polyeta = if eta == 2 { 96 } else { 128 };
+    let mut p = PolyVec::new();
+    for i in 0..N {
+        unpack_eta_poly(eta, &ba[i * polyeta..], &mut p.vec[i]);
     }
+    p
+}
 
-    for i in 0..k as usize {
-        let mut j = 0;
-        loop {
-            if eta == 2 {
-                s2.vec[i].coeffs[j * 8] = (ba[(i + l as usize) * 96 + j * 3] & 0x07) as i32; // 3
-                s2.vec[i].coeffs[j * 8 + 1] =
-                    ((ba[(i + l as usize) * 96 + j * 3] >> 3) & 0x07) as i32; // 3
-                s2.vec[i].coeffs[j * 8 + 2] = ((ba[(i + l as usize) * 96 + j * 3] >> 6) & 0x03)
-                    as i32
-                    | ((ba[(i + l as usize) * 96 + j * 3 + 1] & 0x01) << 2) as i32; // 2 1
-                s2.vec[i].coeffs[j * 8 + 3] =
-                    ((ba[(i + l as usize) * 96 + j * 3 + 1] >> 1) & 0x07) as i32; // 3
-                s2.vec[i].coeffs[j * 8 + 4] =
-                    ((ba[(i + l as usize) * 96 + j * 3 + 1] >> 4) & 0x07) as i32; // 3
-                s2.vec[i].coeffs[j * 8 + 5] = ((ba[(i + l as usize) * 96 + j * 3 + 1] >> 7) & 0x01)
-                    as i32
-                    | ((ba[(i + l as usize) * 96 + j * 3 + 2] & 0x03) << 1) as i32; // 1 2
-                s2.vec[i].coeffs[j * 8 + 6] =
-                    ((ba[(i + l as usize) * 96 + j * 3 + 2] >> 2) & 0x07) as i32; // 3
-                s2.vec[i].coeffs[j * 8 + 7] =
-                    ((ba[(i + l as usize) * 96 + j * 3 + 2] >> 5) & 0x07) as i32; // 3
-
-                s2.vec[i].coeffs[j * 8] = eta - s2.vec[i].coeffs[j * 8];
-                s2.vec[i].coeffs[j * 8 + 1] = eta - s2.vec[i].coeffs[j * 8 + 1];
-                s2.vec[i].coeffs[j * 8 + 2] = eta - s2.vec[i].coeffs[j * 8 + 2];
-                s2.vec[i].coeffs[j * 8 + 3] = eta - s2.vec[i].coeffs[j * 8 + 3];
-                s2.vec[i].coeffs[j * 8 + 4] = eta - s2.vec[i].coeffs[j * 8 + 4];
-                s2.vec[i].coeffs[j * 8 + 5] = eta - s2.vec[i].coeffs[j * 8 + 5];
-                s2.vec[i].coeffs[j * 8 + 6] = eta - s2.vec[i].coeffs[j * 8 + 6];
-                s2.vec[i].coeffs[j * 8 + 7] = eta - s2.vec[i].coeffs[j * 8 + 7];
+// unpack one polynomial of s1 or s2, ba starts with its 96 or 128 bytes
+pub fn unpack_eta_poly(eta: i32, ba: &[u8], p: &mut Poly) {
+    let mut j = 0;
+    loop {
+        if eta == 2 {
+            p.coeffs[j * 8] = (ba[j * 3] & 0x07) as i32; // 3
+            p.coeffs[j * 8 + 1] = ((ba[j * 3] >> 3) & 0x07) as i32; // 3
+            p.coeffs[j * 8 + 2] = ((ba[j * 3] >> 6) & 0x03) as i32
+                | ((ba[j * 3 + 1] & 0x01) << 2) as i32; // 2 1
+            p.coeffs[j * 8 + 3] = ((ba[j * 3 + 1] >> 1) & 0x07) as i32; // 3
+            p.coeffs[j * 8 + 4] = ((ba[j * 3 + 1] >> 4) & 0x07) as i32; // 3
+            p.coeffs[j * 8 + 5] = ((ba[j * 3 + 1] >> 7) & 0x01) as i32
+                | ((ba[j * 3 + 2] & 0x03) << 1) as i32; // 1 2
+            p.coeffs[j * 8 + 6] = ((ba[j * 3 + 2] >> 2) & 0x07) as i32; // 3
+            p.coeffs[j * 8 + 7] = ((ba[j * 3 + 2] >> 5) & 0x07) as i32; // 3
+
+            p.coeffs[j * 8] = eta - p.coeffs[j * 8];
+            p.coeffs[j * 8 + 1] = eta - p.coeffs[j * 8 + 1];
+            p.coeffs[j * 8 + 2] = eta - p.coeffs[j * 8 + 2];
+            p.coeffs[j * 8 + 3] = eta - p.coeffs[j * 8 + 3];
+            p.coeffs[j * 8 + 4] = eta - p.coeffs[j * 8 + 4];
+            p.coeffs[j * 8 + 5] = eta - p.coeffs[j * 8 + 5];
+            p.coeffs[j * 8 + 6] = eta - p.coeffs[j * 8 + 6];
+            p.coeffs[j * 8 + 7] = eta - p.coeffs[j * 8 + 7];
 
-                j += 1;
-                if j * 8 == 256 {
-                    break;
-                }
-            } else if eta == 4 {
-                s2.vec[i].coeffs[j * 2] = (ba[(i + l as usize) * 32 + j * 1] & 0x0F) as i32; // 4
-                s2.vec[i].coeffs[j * 2 + 1] =
-                    ((ba[(i + l as usize) * 32 + j * 1] >> 4) & 0x0F) as i32; // 4
+            j += 1;
+            if j * 8 == 256 {
+                break;
+            }
+        } else if eta == 4 {
+            p.coeffs[j * 2] = (ba[j] & 0x0F) as i32; // 4
+            p.coeffs[j * 2 + 1] = ((ba[j] >> 4) & 0x0F) as i32; // 4
 
-                s2.vec[i].coeffs[j * 2] = eta - s2.vec[i].coeffs[j * 2];
-                s2.vec[i].coeffs[j * 2 + 1] = eta - s2.vec[i].coeffs[j * 2 + 1];
+            p.coeffs[j * 2] = eta - p.coeffs[j * 2];
+            p.coeffs[j * 2 + 1] = eta - p.coeffs[j * 2 + 1];
 
-                j += 1;
-                if j * 2 == 256 {
-                    break;
-                }
-            } else {
-                panic!("eta should be 2 or 4");
+            j += 1;
+            if j * 2 == 256 {
+                break;
             }
+        } else {
+            panic!("eta should be 2 or 4");
         }
     }
-
-    (s1, s2)
 }
 
 // pack to into byte array, coeffs in t0 have 13 bits(in [-2^12, 2^12], 12=d-1)
-// pack 8 coeffs into 13 bytes
-fn pack_t0(t0: &PolyVec) -> Vec<u8> {
-    let mut buf: Vec<u8> = Vec::new();
-
+// pack 8 coeffs into 13 bytes, appended to buf
+fn pack_t0<const K: usize>(t0: &PolyVec<K>, buf: &mut Writer) {
     for i in 0..t0.vec.len() {
         let mut j = 0;
         loop {
@@ -304,57 +264,61 @@ fn pack_t0(t0: &PolyVec) -> Vec<u8> {
             }
         }
     }
-
-    buf
 }
 
 // unpack t0 from byte array, 13 bytes -> 8 coeffs, 13*32=416 bytes for 1 poly
 // t0 has k polys
-fn unpack_t0(k: i32, ba: &Vec<u8>) -> PolyVec {
-    let mut t0 = PolyVec::new(k as usize);
-    for i in 0..k as usize {
-        let mut j = 0;
-        loop {
-            t0.vec[i].coeffs[j * 8] = (ba[i as usize * 416 + j * 13] as i32)
-                | ((ba[i as usize * 416 + j * 13 + 1] as i32 & 0x1F) << 8); // 8 5
-            t0.vec[i].coeffs[j * 8 + 1] = ((ba[i as usize * 416 + j * 13 + 1] as i32 >> 5) & 0x07)
-                | ((ba[i as usize * 416 + j * 13 + 2] as i32) << 3)
-                | ((ba[i as usize * 416 + j * 13 + 3] as i32 & 0x03) << 11); // 3 8 2
-            t0.vec[i].coeffs[j * 8 + 2] = ((ba[i as usize * 416 + j * 13 + 3] as i32 >> 2) & 0x3F)
-                | ((ba[i as usize * 416 + j * 13 + 4] as i32 & 0x7F) << 6); // 6 7
-            t0.vec[i].coeffs[j * 8 + 3] = ((ba[i as usize * 416 + j * 13 + 4] as i32 >> 7) & 0x01)
-                | ((ba[i as usize * 416 + j * 13 + 5] as i32 & 0xFF) << 1)
-                | ((ba[i as usize * 416 + j * 13 + 6] as i32 & 0x0F) << 9); // 1 8 4
-            t0.vec[i].coeffs[j * 8 + 4] = ((ba[i as usize * 416 + j * 13 + 6] as i32 >> 4) & 0x0F)
-                | ((ba[i as usize * 416 + j * 13 + 7] as i32 & 0xFF) << 4)
-                | ((ba[i as usize * 416 + j * 13 + 8] as i32 & 0x01) << 12); // 4 8 1
-            t0.vec[i].coeffs[j * 8 + 5] = ((ba[i as usize * 416 + j * 13 + 8] as i32 >> 1) & 0x7F)
-                | ((ba[i as usize * 416 + j * 13 + 9] as i32 & 0x3F) << 7); // 7 6
-            t0.vec[i].coeffs[j * 8 + 6] = ((ba[i as usize * 416 + j * 13 + 9] as i32 >> 6) & 0x03)
-                | ((ba[i as usize * 416 + j * 13 + 10] as i32 & 0xFF) << 2)
-                | ((ba[i as usize * 416 + j * 13 + 11] as i32 & 0x07) << 10); // 2 8 3
-            t0.vec[i].coeffs[j * 8 + 7] = ((ba[i as usize * 416 + j * 13 + 11] as i32 >> 3) & 0x1F)
-                | ((ba[i as usize * 416 + j * 13 + 12] as i32 & 0xFF) << 5); // 5 8
-
-            t0.vec[i].coeffs[j * 8] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8];
-            t0.vec[i].coeffs[j * 8 + 1] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 1];
-            t0.vec[i].coeffs[j * 8 + 2] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 2];
-            t0.vec[i].coeffs[j * 8 + 3] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 3];
-            t0.vec[i].coeffs[j * 8 + 4] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 4];
-            t0.vec[i].coeffs[j * 8 + 5] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 5];
-            t0.vec[i].coeffs[j * 8 + 6] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 6];
-            t0.vec[i].coeffs[j * 8 + 7] = (1 << (d - 1)) - t0.vec[i].coeffs[j * 8 + 7];
+#[cfg(feature = "alloc")]
+fn unpack_t0<const K: usize>(ba: &[u8]) -> PolyVec<K> {
+    let mut t0 = PolyVec::new();
+    for i in 0..K {
+        unpack_t0_poly(&ba[i * 416..], &mut t0.vec[i]);
+    }
+    t0
+}
 
-            j += 1;
-            if j * 8 == 256 {
-                break;
-            }
+// unpack one polynomial of t0, ba starts with its 416 bytes
+pub fn unpack_t0_poly(ba: &[u8], t0: &mut Poly) {
+    let mut j = 0;
+    loop {
+        t0.coeffs[j * 8] = (ba[j * 13] as i32)
+            | ((ba[j * 13 + 1] as i32 & 0x1F) << 8); // 8 5
+        t0.coeffs[j * 8 + 1] = ((ba[j * 13 + 1] as i32 >> 5) & 0x07)
+            | ((ba[j * 13 + 2] as i32) << 3)
+            | ((ba[j * 13 + 3] as i32 & 0x03) << 11); // 3 8 2
+        t0.coeffs[j * 8 + 2] = ((ba[j * 13 + 3] as i32 >> 2) & 0x3F)
+            | ((ba[j * 13 + 4] as i32 & 0x7F) << 6); // 6 7
+        t0.coeffs[j * 8 + 3] = ((ba[j * 13 + 4] as i32 >> 7) & 0x01)
+            | ((ba[j * 13 + 5] as i32 & 0xFF) << 1)
+            | ((ba[j * 13 + 6] as i32 & 0x0F) << 9); // 1 8 4
+        t0.coeffs[j * 8 + 4] = ((ba[j * 13 + 6] as i32 >> 4) & 0x0F)
+            | ((ba[j * 13 + 7] as i32 & 0xFF) << 4)
+            | ((ba[j * 13 + 8] as i32 & 0x01) << 12); // 4 8 1
+        t0.coeffs[j * 8 + 5] = ((ba[j * 13 + 8] as i32 >> 1) & 0x7F)
+            | ((ba[j * 13 + 9] as i32 & 0x3F) << 7); // 7 6
+        t0.coeffs[j * 8 + 6] = ((ba[j * 13 + 9] as i32 >> 6) & 0x03)
+            | ((ba[j * 13 + 10] as i32 & 0xFF) << 2)
+            | ((ba[j * 13 + 11] as i32 & 0x07) << 10); // 2 8 3
+        t0.coeffs[j * 8 + 7] = ((ba[j * 13 + 11] as i32 >> 3) & 0x1F)
+            | ((ba[j * 13 + 12] as i32 & 0xFF) << 5); // 5 8
+
+        t0.coeffs[j * 8] = (1 << (d - 1)) - t0.coeffs[j * 8];
+        t0.coeffs[j * 8 + 1] = (1 << (d - 1)) - t0.coeffs[j * 8 + 1];
+        t0.coeffs[j * 8 + 2] = (1 << (d - 1)) - t0.coeffs[j * 8 + 2];
+        t0.coeffs[j * 8 + 3] = (1 << (d - 1)) - t0.coeffs[j * 8 + 3];
+        t0.coeffs[j * 8 + 4] = (1 << (d - 1)) - t0.coeffs[j * 8 + 4];
+        t0.coeffs[j * 8 + 5] = (1 << (d - 1)) - t0.coeffs[j * 8 + 5];
+        t0.coeffs[j * 8 + 6] = (1 << (d - 1)) - t0.coeffs[j * 8 + 6];
+        t0.coeffs[j * 8 + 7] = (1 << (d - 1)) - t0.coeffs[j * 8 + 7];
+
+        j += 1;
+        if j * 8 == 256 {
+            break;
         }
     }
-    t0
 }
 
-pub fn unpack_y(gamma1: i32, ba: &Vec<u8>) -> Poly {
+pub fn unpack_y(gamma1: i32, ba: &[u8]) -> Poly {
     let mut y = Poly::new();
     let mut i = 0;
 
@@ -373,7 +337,7 @@ pub fn unpack_y(gamma1: i32, ba: &Vec<u8>) -> Poly {
                 | (((ba[i * 9 + 6] & 0x3F) as i32) << 12); // 4 8 6
             y.coeffs[i * 4 + 3] = ((ba[i * 9 + 6] >> 6) & 0x03) as i32
                 | ((ba[i * 9 + 7] as i32) << 2)
-                | (((ba[i * 9 + 8] & 0xFF) as i32) << 10); // 2 8 8
+                | ((ba[i * 9 + 8] as i32) << 10); // 2 8 8
 
             y.coeffs[i * 4] = gamma1 - y.coeffs[i * 4];
             y.coeffs[i * 4 + 1] = gamma1 - y.coeffs[i * 4 + 1];
@@ -397,8 +361,8 @@ pub fn unpack_y(gamma1: i32, ba: &Vec<u8>) -> Poly {
                 | ((ba[i * 5 + 3] as i32) << 4)
                 | ((ba[i * 5 + 4] as i32) << 12); // 4 8 8
 
-            y.coeffs[i * 2] = gamma1 - y.coeffs[i];
-            y.coeffs[i * 2 + 1] = gamma1 - y.coeffs[i + 1];
+            y.coeffs[i * 2] = gamma1 - y.coeffs[i * 2];
+            y.coeffs[i * 2 + 1] = gamma1 - y.coeffs[i * 2 + 1];
 
             i += 1;
             if i * 2 == 256 {
@@ -412,12 +376,11 @@ pub fn unpack_y(gamma1: i32, ba: &Vec<u8>) -> Poly {
     y
 }
 
-pub fn pack_w1(w1: &PolyVec, gamma2: i32, k: i32) -> Vec<u8> {
+pub fn pack_w1<const K: usize>(w1: &PolyVec<K>, gamma2: i32, buf: &mut [u8]) {
     // coeff of w1 is in [0, 43], takes 6 bits, k*256*6/8
     // 4 coeffs into 3 bytes
     if gamma2 == 95232 {
-        let mut buf = vec![0u8; 192 * k as usize];
-        for i in 0..w1.len {
+        for i in 0..K {
             let mut j = 0;
             loop {
                 buf[i * 192 + j * 3] = w1.vec[i].coeffs[j * 4] as u8
@@ -433,13 +396,10 @@ pub fn pack_w1(w1: &PolyVec, gamma2: i32, k: i32) -> Vec<u8> {
                 }
             }
         }
-
-        buf
     }
     // coeff of w1 is in [0, 15], takes 4 bits, k*256*4/8
     else if gamma2 == 261888 {
-        let mut buf = vec![0u8; 128 * k as usize];
-        for i in 0..w1.len {
+        for i in 0..K {
             let mut j = 0;
             loop {
                 buf[i * 128 + j] =
@@ -450,73 +410,72 @@ pub fn pack_w1(w1: &PolyVec, gamma2: i32, k: i32) -> Vec<u8> {
                 }
             }
         }
-        buf
     } else {
-        panic!("ga");
+        panic!("gamma2 not supported");
     }
 }
 
-fn pack_z(z: &PolyVec, level: i32) -> Vec<u8> {
-    // coeff of z is in [-2^17-1, 2^17], takes 18 bits, 256*18/8*l, l= 4
+#[cfg(all(test, feature = "std"))]
+fn pack_z<const L: usize>(z: &PolyVec<L>, gamma1: i32, buf: &mut [u8]) {
+    let polyz = if gamma1 == 1 << 17 { 576 } else { 640 };
+    for i in 0..L {
+        pack_z_poly(&z.vec[i], gamma1, &mut buf[i * polyz..]);
+    }
+}
+
+// pack one polynomial of z into the first 576 or 640 bytes of buf
+pub fn pack_z_poly(z: &Poly, gamma1: i32, buf: &mut [u8]) {
+    // coeff of z is in [-2^17-1, 2^17], takes 18 bits, 256*18/8 bytes
     // pack 4 coeffs into 9 bytes
-    if level == 2 {
-        let mut buf = vec![0u8; 576 * 4];
-        for i in 0..z.len {
-            let mut j = 0;
-            loop {
-                let a0 = (1 << 17) - z.vec[i].coeffs[j * 4];
-                let a1 = (1 << 17) - z.vec[i].coeffs[j * 4 + 1];
-                let a2 = (1 << 17) - z.vec[i].coeffs[j * 4 + 2];
-                let a3 = (1 << 17) - z.vec[i].coeffs[j * 4 + 3];
-
-                buf[i * 576 + j * 9] = a0 as u8; // 8
-                buf[i * 576 + j * 9 + 1] = (a0 >> 8) as u8; // 8
-                buf[i * 576 + j * 9 + 2] = ((a0 >> 16) & 0x03) as u8 | ((a1 & 0x3F) << 2) as u8; // 2 6
-                buf[i * 576 + j * 9 + 3] = (a1 >> 6) as u8; // 8
-                buf[i * 576 + j * 9 + 4] = ((a1 >> 14) & 0x0F) as u8 | ((a2 & 0x0F) << 4) as u8; // 4 4
-                buf[i * 576 + j * 9 + 5] = (a2 >> 4) as u8; // 8
-                buf[i * 576 + j * 9 + 6] = ((a2 >> 12) & 0x3F) as u8 | ((a3 & 0x03) << 6) as u8; // 6 2
-                buf[i * 576 + j * 9 + 7] = (a3 >> 2) as u8; // 8
-                buf[i * 576 + j * 9 + 8] = (a3 >> 10) as u8; // 8
-                j += 1;
-                if j * 4 == 256 {
-                    break;
-                }
+    if gamma1 == 1 << 17 {
+        let mut j = 0;
+        loop {
+            let a0 = (1 << 17) - z.coeffs[j * 4];
+            let a1 = (1 << 17) - z.coeffs[j * 4 + 1];
+            let a2 = (1 << 17) - z.coeffs[j * 4 + 2];
+            let a3 = (1 << 17) - z.coeffs[j * 4 + 3];
+
+            buf[j * 9] = a0 as u8; // 8
+            buf[j * 9 + 1] = (a0 >> 8) as u8; // 8
+            buf[j * 9 + 2] = ((a0 >> 16) & 0x03) as u8 | ((a1 & 0x3F) << 2) as u8; // 2 6
+            buf[j * 9 + 3] = (a1 >> 6) as u8; // 8
+            buf[j * 9 + 4] = ((a1 >> 14) & 0x0F) as u8 | ((a2 & 0x0F) << 4) as u8; // 4 4
+            buf[j * 9 + 5] = (a2 >> 4) as u8; // 8
+            buf[j * 9 + 6] = ((a2 >> 12) & 0x3F) as u8 | ((a3 & 0x03) << 6) as u8; // 6 2
+            buf[j * 9 + 7] = (a3 >> 2) as u8; // 8
+            buf[j * 9 + 8] = (a3 >> 10) as u8; // 8
+            j += 1;
+            if j * 4 == 256 {
+                break;
             }
         }
-        buf
     }
-    // coeff of z is in [-2^19-1, 2^19], takes 20 bits, 256*20/8*l, l=5
+    // coeff of z is in [-2^19-1, 2^19], takes 20 bits, 256*20/8 bytes
     // pack 2 coeffs into 5 bytes
-    else if level == 3 || level == 5 {
-        let mut buf = vec![0u8; 640 * z.len as usize];
-        for i in 0..z.len {
-            let mut j = 0;
-            loop {
-                let a0 = (1 << 19) - z.vec[i].coeffs[j * 2];
-                let a1 = (1 << 19) - z.vec[i].coeffs[j * 2 + 1];
-
-                buf[i * 640 + j * 5] = a0 as u8; // 8
-                buf[i * 640 + j * 5 + 1] = (a0 >> 8) as u8; // 8
-                buf[i * 640 + j * 5 + 2] = ((a0 >> 16) & 0x0F) as u8 | ((a1 & 0x0F) << 4) as u8; // 4 4
-                buf[i * 640 + j * 5 + 3] = (a1 >> 4) as u8; // 8
-                buf[i * 640 + j * 5 + 4] = (a1 >> 12) as u8; // 8
-                j += 1;
-                if j * 2 == 256 {
-                    break;
-                }
+    else if gamma1 == 1 << 19 {
+        let mut j = 0;
+        loop {
+            let a0 = (1 << 19) - z.coeffs[j * 2];
+            let a1 = (1 << 19) - z.coeffs[j * 2 + 1];
+
+            buf[j * 5] = a0 as u8; // 8
+            buf[j * 5 + 1] = (a0 >> 8) as u8; // 8
+            buf[j * 5 + 2] = ((a0 >> 16) & 0x0F) as u8 | ((a1 & 0x0F) << 4) as u8; // 4 4
+            buf[j * 5 + 3] = (a1 >> 4) as u8; // 8
+            buf[j * 5 + 4] = (a1 >> 12) as u8; // 8
+            j += 1;
+            if j * 2 == 256 {
+                break;
             }
         }
-        buf
     } else {
-        panic!("level is not 2, 3, 5");
+        panic!("gamma1 is not 2^17 or 2^19");
     }
 }
 
-fn unpack_z(z_ba: &Vec<u8>, level: i32) -> PolyVec {
-    if level == 2 {
-        let mut z = PolyVec::new(4);
-        for i in 0..z.len {
+fn unpack_z<const L: usize>(z_ba: &[u8], gamma1: i32, z: &mut PolyVec<L>) {
+    if gamma1 == 1 << 17 {
+        for i in 0..L {
             let mut j = 0;
             loop {
                 let a0 = z_ba[i * 576 + j * 9] as i32
@@ -541,10 +500,8 @@ fn unpack_z(z_ba: &Vec<u8>, level: i32) -> PolyVec {
                 }
             }
         }
-        z
-    } else if level == 3 {
-        let mut z = PolyVec::new(5);
-        for i in 0..z.len {
+    } else if gamma1 == 1 << 19 {
+        for i in 0..L {
             let mut j = 0;
             loop {
                 let a0 = z_ba[i * 640 + j * 5] as i32
@@ -561,96 +518,158 @@ fn unpack_z(z_ba: &Vec<u8>, level: i32) -> PolyVec {
                 }
             }
         }
-        z
-    } else if level == 5 {
-        let mut z = PolyVec::new(7);
-        for i in 0..z.len {
-            let mut j = 0;
-            loop {
-                let a0 = z_ba[i * 640 + j * 5] as i32
-                    | ((z_ba[i * 640 + j * 5 + 1] as i32) << 8)
-                    | (((z_ba[i * 640 + j * 5 + 2] & 0x0F) as i32) << 16);
-                let a1 = (z_ba[i * 640 + j * 5 + 2] >> 4) as i32
-                    | (z_ba[i * 640 + j * 5 + 3] as i32) << 4
-                    | ((z_ba[i * 640 + j * 5 + 4] as i32) << 12);
-                z.vec[i].coeffs[j * 2] = (1 << 19) - a0;
-                z.vec[i].coeffs[j * 2 + 1] = (1 << 19) - a1;
-                j += 1;
-                if j * 2 == 256 {
-                    break;
-                }
-            }
-        }
-        z
     } else {
-        panic!("level is not 2, 3, 5");
+        panic!("gamma1 is not 2^17 or 2^19");
     }
 }
 
 // h has k polynomials, each contains no more than tau 1's, pack these locations of 1, and record how much 1's in each polynomial at the end with h.len bytes
-pub fn pack_delta(cp: &[u8; 32], z: &PolyVec, h: &PolyVec, level: i32, omega: i32) -> Vec<u8> {
-    let mut buf = cp.to_vec();
-    buf.append(&mut pack_z(z, level));
-    let mut cnt = vec![0u8; h.len];
-    for i in 0..h.len {
-        if i > 0 {
-            cnt[i] = cnt[i - 1];
-        }
+// signing packs z as it computes it and the hints with pack_hints, this is for the tests
+#[cfg(all(test, feature = "std"))]
+pub fn pack_delta<const K: usize, const L: usize>(
+    cp: &[u8],
+    z: &PolyVec<L>,
+    h: &PolyVec<K>,
+    gamma1: i32,
+    omega: i32,
+    delta: &mut [u8],
+) {
+    let polyz = if gamma1 == 1 << 17 { 576 } else { 640 };
+    let (ctilde, buf) = delta.split_at_mut(cp.len());
+    ctilde.copy_from_slice(cp);
+    let (z_ba, buf) = buf.split_at_mut(polyz * L);
+    pack_z(z, gamma1, z_ba);
+    pack_hints(h, omega, buf);
+}
+
+// the positions of the ones of h in the first omega bytes of buf and the running counts in the last k bytes
+pub fn pack_hints<const K: usize>(h: &PolyVec<K>, omega: i32, buf: &mut [u8]) {
+    let (index, cnt) = buf.split_at_mut(omega as usize);
+    index.fill(0);
+    let mut n = 0;
+    for i in 0..K {
         for j in 0..256 {
             if h.vec[i].coeffs[j] == 1 {
-                buf.push(j as u8);
-                cnt[i] += 1;
+                index[n] = j as u8;
+                n += 1;
             }
         }
+        cnt[i] = n as u8;
     }
-    buf.append(vec![0u8; (omega - cnt[cnt.len() - 1] as i32) as usize].as_mut());
-    buf.append(&mut cnt);
-    buf
 }
 
-pub fn unpack_delta(delta: &Vec<u8>, k: i32, l: i32, omega: i32) -> ([u8; 32], PolyVec, PolyVec) {
-    let mut cp = [0u8; 32];
-    let mut z = PolyVec::new(l as usize);
-    let mut h = PolyVec::new(k as usize);
-
-    let buf = delta;
-    cp.copy_from_slice(&buf[0..32]);
-    if l == 4 {
-        z = unpack_z(&buf[32..32 + 576 * 4].to_vec(), 2);
-        let buf = &buf[32 + 576 * 4..].to_vec();
-        let cnt = buf[omega as usize..omega as usize + k as usize].to_vec();
-        let mut c = 0;
-        for i in 0..cnt.len() {
-            for j in c..cnt[i] {
-                h.vec[i].coeffs[buf[j as usize] as usize] = 1;
-            }
-            c = cnt[i];
-        }
-    } else if l == 5 {
-        z = unpack_z(&buf[32..32 + 640 * 5].to_vec(), 2);
-        let buf = &buf[32 + 640 * 5..].to_vec();
-        let cnt = buf[omega as usize..omega as usize + k as usize].to_vec();
-        let mut c = 0;
-        for i in 0..cnt.len() {
-            for j in c..cnt[i] {
-                h.vec[i].coeffs[buf[j as usize] as usize] = 1;
-            }
-            c = cnt[i];
+// cp has 32 bytes in dilithium and lambda/4 bytes in ML-DSA
+// the decoding is strict as HintBitUnpack of FIPS 204, so every signature has exactly one encoding:
+// the counts are non-decreasing and at most omega, the indices of a polynomial are strictly increasing
+// and the unused index bytes are zero
+// z needs no check, every 18 or 20 bits pattern decodes into (-gamma1, gamma1], verify checks its norm
+// z and h are written into the caller's vectors and c tilde is returned
+pub fn unpack_delta<'a, const K: usize, const L: usize>(
+    delta: &'a [u8],
+    gamma1: i32,
+    omega: i32,
+    ctildebytes: usize,
+    z: &mut PolyVec<L>,
+    h: &mut PolyVec<K>,
+) -> Result<&'a [u8], Error> {
+    // z is packed with 18 bits per coeff for gamma1 = 2^17 and 20 bits for gamma1 = 2^19
+    let polyz = if gamma1 == 1 << 17 { 576 } else { 640 };
+    let omega = omega as usize;
+    if delta.len() != ctildebytes + polyz * L + omega + K {
+        return Err(Error::InvalidLength(delta.len()));
+    }
+
+    let cp = &delta[0..ctildebytes];
+    let buf = &delta[ctildebytes..];
+    unpack_z(&buf[..polyz * L], gamma1, z);
+    let buf = &buf[polyz * L..];
+    let (index, cnt) = buf.split_at(omega);
+
+    h.zeroize();
+    let mut c = 0;
+    for (i, &n) in cnt.iter().enumerate() {
+        let n = n as usize;
+        if n < c || n > omega {
+            return Err(Error::MalformedEncoding);
         }
-    } else if l == 7 {
-        z = unpack_z(&buf[32..32 + 640 * 7].to_vec(), 2);
-        let buf = &buf[32 + 640 * 7..].to_vec();
-        let cnt = buf[omega as usize..omega as usize + k as usize].to_vec();
-        let mut c = 0;
-        for i in 0..cnt.len() {
-            for j in c..cnt[i] {
-                h.vec[i].coeffs[buf[j as usize] as usize] = 1;
+        for j in c..n {
+            if j > c && index[j - 1] >= index[j] {
+                return Err(Error::MalformedEncoding);
             }
-            c = cnt[i];
+            h.vec[i].coeffs[index[j] as usize] = 1;
         }
-    } else {
-        panic!("l is not 4, 5, 7");
+        c = n;
+    }
+    if index[c..].iter().any(|&b| b != 0) {
+        return Err(Error::MalformedEncoding);
+    }
+
+    Ok(cp)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::sign::{key_pair, sign};
+
+    // a level 2 round 3 signature, whose hint section is the last omega + k = 84 bytes
+    fn signature() -> Vec<u8> {
+        let (_, sk) = key_pair(&[7u8; 32], 2).unwrap();
+        sign(&sk, b"message", 2).unwrap()
+    }
+
+    fn unpack(delta: &[u8]) -> Result<(&[u8], PolyVec<4>, PolyVec<4>), Error> {
+        let (mut z, mut h) = (PolyVec::new(), PolyVec::new());
+        let cp = unpack_delta::<4, 4>(delta, 1 << 17, 80, 32, &mut z, &mut h)?;
+        Ok((cp, z, h))
     }
 
-    (cp, z, h)
+    #[test]
+    fn test_unpack_delta_round_trip() {
+        let delta = signature();
+        let (cp, z, h) = unpack(&delta).unwrap();
+        let mut packed = vec![0u8; delta.len()];
+        pack_delta(cp, &z, &h, 1 << 17, 80, &mut packed);
+        assert_eq!(packed, delta);
+    }
+
+    #[test]
+    fn test_unpack_delta_strict() {
+        let delta = signature();
+        let n = delta.len();
+        let hints = n - 84;
+        let cnt = &delta[n - 4..];
+        assert!(cnt[3] >= 2, "the test needs at least 2 hints");
+
+        assert_eq!(unpack(&delta[..n - 1]).unwrap_err(), Error::InvalidLength(n - 1));
+        let mut longer = delta.clone();
+        longer.push(0);
+        assert_eq!(unpack(&longer).unwrap_err(), Error::InvalidLength(n + 1));
+
+        // a count above omega
+        let mut bad = delta.clone();
+        bad[n - 1] = 81;
+        assert_eq!(unpack(&bad).unwrap_err(), Error::MalformedEncoding);
+
+        // decreasing counts
+        let mut bad = delta.clone();
+        bad[n - 4] = bad[n - 1] + 1;
+        assert_eq!(unpack(&bad).unwrap_err(), Error::MalformedEncoding);
+
+        // a non-zero byte after the last index
+        let mut bad = delta.clone();
+        bad[hints + cnt[3] as usize] = 1;
+        assert_eq!(unpack(&bad).unwrap_err(), Error::MalformedEncoding);
+
+        // the indices of the polynomial holding the first two hints are swapped or repeated
+        let i = cnt.iter().position(|&c| c >= 2).unwrap();
+        let first = if i == 0 { 0 } else { cnt[i - 1] as usize };
+        if cnt[i] as usize - first >= 2 {
+            let mut bad = delta.clone();
+            bad.swap(hints + first, hints + first + 1);
+            assert_eq!(unpack(&bad).unwrap_err(), Error::MalformedEncoding);
+            bad[hints + first] = bad[hints + first + 1];
+            assert_eq!(unpack(&bad).unwrap_err(), Error::MalformedEncoding);
+        }
+    }
 }
@@ -6,24 +6,146 @@ pub static MONT:i32 = -4186625; // R mod q;
 
 pub static d: u8 = 13;
 
-pub fn get_params(level: u8) -> (i32, i32, i32) //k, l, eta
+// the parameter sets of FIPS 204, the round 3 dilithium levels 2, 3 and 5 use the same values
+// the byte sizes are those of ML-DSA, dilithium has a 32 bytes tr and c tilde
+pub trait ParameterSet {
+    const LEVEL: u8;
+    const K: usize;
+    const L: usize;
+    const ETA: i32;
+    const GAMMA1: i32;
+    const GAMMA2: i32;
+    const TAU: i32;
+    const BETA: i32 = Self::TAU * Self::ETA;
+    const OMEGA: i32;
+    // c tilde has lambda/4 bytes
+    const CTILDE_BYTES: usize;
+    // the signer multiplies the challenge c with s1, s2 and t0 as a sparse polynomial instead of in the NTT domain,
+    // both give the same signatures, the choice follows test_challenge_mul_speed in tests/test_speed.rs
+    const SPARSE_CHALLENGE: bool;
+
+    const POLY_ETA_PACKED_BYTES: usize = if Self::ETA == 2 { 96 } else { 128 };
+    const POLY_Z_PACKED_BYTES: usize = if Self::GAMMA1 == 1 << 17 { 576 } else { 640 };
+    const POLY_W1_PACKED_BYTES: usize = if Self::GAMMA2 == 95232 { 192 } else { 128 };
+    const PK_BYTES: usize = 32 + 320 * Self::K;
+    const SK_BYTES: usize = 128 + (Self::K + Self::L) * Self::POLY_ETA_PACKED_BYTES + 416 * Self::K;
+    const SIG_BYTES: usize =
+        Self::CTILDE_BYTES + Self::L * Self::POLY_Z_PACKED_BYTES + Self::OMEGA as usize + Self::K;
+}
+
+// security level 2
+pub struct MlDsa44;
+
+// security level 3
+pub struct MlDsa65;
+
+// security level 5
+pub struct MlDsa87;
+
+impl ParameterSet for MlDsa44 {
+    const LEVEL: u8 = 2;
+    const K: usize = 4;
+    const L: usize = 4;
+    const ETA: i32 = 2;
+    const GAMMA1: i32 = 1 << 17;
+    const GAMMA2: i32 = 95232; // (q-1)/88
+    const TAU: i32 = 39;
+    const OMEGA: i32 = 80;
+    const CTILDE_BYTES: usize = 32;
+    const SPARSE_CHALLENGE: bool = true;
+}
+
+impl ParameterSet for MlDsa65 {
+    const LEVEL: u8 = 3;
+    const K: usize = 6;
+    const L: usize = 5;
+    const ETA: i32 = 4;
+    const GAMMA1: i32 = 1 << 19;
+    const GAMMA2: i32 = 261888; // (q-1)/32
+    const TAU: i32 = 49;
+    const OMEGA: i32 = 55;
+    const CTILDE_BYTES: usize = 48;
+    const SPARSE_CHALLENGE: bool = true;
+}
+
+impl ParameterSet for MlDsa87 {
+    const LEVEL: u8 = 5;
+    const K: usize = 8;
+    const L: usize = 7;
+    const ETA: i32 = 2;
+    const GAMMA1: i32 = 1 << 19;
+    const GAMMA2: i32 = 261888; // (q-1)/32
+    const TAU: i32 = 60;
+    const OMEGA: i32 = 75;
+    const CTILDE_BYTES: usize = 64;
+    const SPARSE_CHALLENGE: bool = false; // 60 coefficients of c times 22 polynomials, the NTT is faster
+}
+
+// calls f::<P, K, L>(args) with the parameter set of a runtime security level, other levels panic
+// K and L are passed separately because P::K cannot be used as a const generic argument
+macro_rules! with_parameter_set {
+    ($level:expr, $f:ident($($arg:expr),* $(,)?)) => {{
+        use $crate::params::{MlDsa44, MlDsa65, MlDsa87, ParameterSet};
+        match $level {
+            2 => $f::<MlDsa44, { MlDsa44::K }, { MlDsa44::L }>($($arg),*),
+            3 => $f::<MlDsa65, { MlDsa65::K }, { MlDsa65::L }>($($arg),*),
+            5 => $f::<MlDsa87, { MlDsa87::K }, { MlDsa87::L }>($($arg),*),
+            _ => panic!("security level not supported"),
+        }
+    }};
+}
+pub(crate) use with_parameter_set;
+
+// the round 3 submission of dilithium, or the final FIPS 204 standard (ML-DSA)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Dilithium,
+    MlDsa,
+}
+
+pub fn get_mode_params<P: ParameterSet>(mode: Mode) -> (usize, usize) //tr bytes, c tilde bytes
 {
-    match level {
-        2 => (4, 4, 2),
-        3 => (6, 5, 4),
-        5 => (8, 7, 2),
-        // others will panic
-        _ => panic!("security level not supported"),
+    match mode {
+        Mode::Dilithium => (32, 32),
+        Mode::MlDsa => (64, P::CTILDE_BYTES),
     }
 }
 
-pub fn get_params_sign(level: u8) -> (i32, i32, i32, i32, i32, i32, i32) //k, l, eta, gamma1, gamma2, tau, beta, omega
+fn sizes<P: ParameterSet, const K: usize, const L: usize>(mode: Mode) -> (usize, usize, usize) {
+    let (trbytes, ctildebytes) = get_mode_params::<P>(mode);
+    (
+        P::PK_BYTES,
+        P::SK_BYTES - 64 + trbytes,
+        P::SIG_BYTES - P::CTILDE_BYTES + ctildebytes,
+    )
+}
+
+pub fn get_sizes(level: u8, mode: Mode) -> (usize, usize, usize) //pk bytes, sk bytes, signature bytes
+{
+    with_parameter_set!(level, sizes(mode))
+}
+
+// infer the security level from the lengths of a packed public key and signature
+pub fn get_level(pk_len: usize, sig_len: usize, mode: Mode) -> Option<u8>
 {
-    match level {
-        2 => (4, 4, 2, 1<<17, 95232, 39, 80),
-        3 => (6, 5, 4, 1<<19, 261888, 49, 55),
-        5 => (8, 7, 2, 1<<19, 261888, 60, 75),
-        // others will panic
-        _ => panic!("security level not supported"),
+    [2, 3, 5].into_iter().find(|&level| {
+        let (pk, _, sig) = get_sizes(level, mode);
+        pk == pk_len && sig == sig_len
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(get_sizes(2, Mode::Dilithium), (1312, 2528, 2420));
+        assert_eq!(get_sizes(3, Mode::Dilithium), (1952, 4000, 3293));
+        assert_eq!(get_sizes(5, Mode::Dilithium), (2592, 4864, 4595));
+        assert_eq!(get_sizes(2, Mode::MlDsa), (1312, 2560, 2420));
+        assert_eq!(get_sizes(3, Mode::MlDsa), (1952, 4032, 3309));
+        assert_eq!(get_sizes(5, Mode::MlDsa), (2592, 4896, 4627));
+        assert_eq!((MlDsa44::BETA, MlDsa65::BETA, MlDsa87::BETA), (78, 196, 120));
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,10 @@
-use crate::reduce::montgomery_reduce;
+#[cfg(target_arch = "x86_64")]
+use crate::avx2;
+use crate::reduce::{mod_q, montgomery_reduce, reduce32};
 use crate::rounding::{high_bits, low_bits};
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
-static ZETAS: [i32; 255] = [25847, -2608894, -518909, 237124, -777960, -876248, 
+pub(crate) static ZETAS: [i32; 255] = [25847, -2608894, -518909, 237124, -777960, -876248, 
 466468, 1826347, 2353451, -359251, -2091905, 3119733, -2884855, 3111497, 2680103, 2725464, 1024112, -1079900, 3585928, -549488, -1119584, 2619752, -2108549, -2118186, -3859737, -1399561, -3277672, 1757237, -19422, 4010497, 280005, 2706023, 95776, 3077325, 3530437, -1661693, -3592148, -2537516, 3915439, -3861115, -3043716, 3574422, -2867647, 3539968, -300467, 2348700, -539299, -1699267, -1643818, 3505694, -3821735, 3507263, -2140649, -1600420, 3699596, 811944, 531354, 954230, 3881043, 3900724, -2556880, 2071892, -2797779, -3930395, -1528703, -3677745, -3041255, -1452451, 3475950, 2176455, -1585221, -1257611, 1939314, -4083598, -1000202, -3190144, -3157330, -3632928, 126922, 3412210, -983419, 2147896, 2715295, -2967645, -3693493, -411027, -2477047, -671102, -1228525, -22981, -1308169, -381987, 1349076, 1852771, -1430430, -3343383, 264944, 508951, 3097992, 44288, -1100098, 904516, 3958618, -3724342, -8578, 1653064, -3249728, 2389356, -210977, 759969, -1316856, 189548, -3553272, 3159746, -1851402, -2409325, -177440, 1315589, 1341330, 1285669, -1584928, -812732, -1439742, -3019102, -3881060, -3628969, 3839961, 2091667, 3407706, 2316500, 3817976, -3342478, 2244091, -2446433, -3562462, 266997, 2434439, -1235728, 3513181, -3520352, -3759364, -1197226, -3193378, 900702, 1859098, 909542, 819034, 495491, -1613174, -43260, -522500, -655327, -3122442, 2031748, 3207046, -3556995, -525098, -768622, -3595838, 342297, 286988, -2437823, 4108315, 3437287, -3342277, 1735879, 203044, 2842341, 2691481, -2590150, 1265009, 4055324, 1247620, 2486353, 1595974, -3767016, 1250494, 2635921, -3548272, -2994039, 1869119, 1903435, -1050970, -1333058, 1237275, -3318210, -1430225, -451100, 1312455, 3306115, -1962642, -1279661, 1917081, -2546312, -1374803, 1500165, 777191, 2235880, 3406031, -542412, -2831860, -1671176, -1846953, -2584293, -3724270, 594136, -3776993, -2013608, 2432395, 2454455, -164721, 1957272, 3369112, 185531, -1207385, -3183426, 162844, 1616392, 3014001, 810149, 1652634, -3694233, -1799107, -3038916, 3523897, 3866901, 269760, 2213111, -975884, 1717735, 472078, -426683, 1723600, -1803090, 1910376, -1667432, -1104333, -260646, -3833893, -2939036, -2235985, -420899, -2286327, 
 183443, -976891, 1612842, -3545687, -554416, 3919660, -48306, -1362209, 
 3937738, 1400424, -846154, 1976782];
@@ -9,67 +12,128 @@ static ZETAS: [i32; 255] = [25847, -2608894, -518909, 237124, -777960, -876248,
 static Q: i32 = 8380417;
 const F: i32 = 41978; // mont^2 /256
 
-#[derive(Debug, Copy, Clone)]
+// a polynomial may hold secret coefficients, e.g. of s1 or y, so it is not Copy and is wiped when dropped
+#[derive(Clone)]
 pub struct Poly {
     pub coeffs: [i32; 256],
 }
 
+// the coefficients are not printed
+impl core::fmt::Debug for Poly {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Poly").finish_non_exhaustive()
+    }
+}
+
+impl Zeroize for Poly {
+    fn zeroize(&mut self) {
+        self.coeffs.zeroize();
+    }
+}
+
+impl Drop for Poly {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for Poly {}
+
+impl Default for Poly {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the arithmetic works in place and reduces lazily, the comments give the bounds on the coefficients each method
+// needs and returns, the by-value add, sub, neg, ntt, intt and point_wise_mul copy self and are kept for callers
+// that want a fresh polynomial
 impl Poly {
     pub fn new() -> Poly {
         Poly { coeffs: [0; 256] }
     }
 
+    // self += a * b * R^{-1} in the NTT domain, without reduction of the sum
+    // |a * b| < Q * 2^31 gives products in (-Q, Q), e.g. a in (-9Q, 9Q) from ntt_in_place and b in (-Q, Q)
+    pub fn point_wise_mul_acc(&mut self, a: &Poly, b: &Poly) {
+        debug_assert!(a.coeffs.iter().zip(&b.coeffs).all(|(&a, &b)| (a as i64 * b as i64).abs() < (Q as i64) << 31));
+        #[cfg(target_arch = "x86_64")]
+        if avx2::available() {
+            // the CPU has AVX2
+            unsafe { avx2::point_wise_mul_acc(&mut self.coeffs, &a.coeffs, &b.coeffs) };
+            return;
+        }
+        self.point_wise_mul_acc_scalar(a, b)
+    }
 
-    pub fn point_wise_mul(&self, b: &Poly) -> Poly {
-        let mut i: usize = 0;
-        let mut c: Poly = Poly::new();
-        loop {
-            c.coeffs[i] = montgomery_reduce(self.coeffs[i] as i64 * b.coeffs[i] as i64); // mont(a, b) = abR^{-1}
-            i += 1;
-            if i == 256 {
-                break;
-            }
+    pub(crate) fn point_wise_mul_acc_scalar(&mut self, a: &Poly, b: &Poly) {
+        for i in 0..256 {
+            self.coeffs[i] += montgomery_reduce(a.coeffs[i] as i64 * b.coeffs[i] as i64); // mont(a, b) = abR^{-1}
         }
+    }
+
+    pub fn point_wise_mul(&self, b: &Poly) -> Poly {
+        let mut c = Poly::new();
+        c.point_wise_mul_acc(self, b);
         c
     }
 
-    pub fn add(&self, b: &Poly) -> Poly {
-        let mut c: Poly = Poly::new();
-        let mut i: usize = 0;
-        loop {
-            c.coeffs[i] = (self.coeffs[i] + b.coeffs[i]) % Q;
-            i += 1;
-            if i == 256 {
-                break;
-            }
+    // self += b, |self| + |b| must stay below 2^31
+    pub fn add_assign(&mut self, b: &Poly) {
+        for i in 0..256 {
+            self.coeffs[i] += b.coeffs[i];
+        }
+    }
+
+    // self -= b, |self| + |b| must stay below 2^31
+    pub fn sub_assign(&mut self, b: &Poly) {
+        for i in 0..256 {
+            self.coeffs[i] -= b.coeffs[i];
+        }
+    }
+
+    pub fn neg_assign(&mut self) {
+        for coeff in self.coeffs.iter_mut() {
+            *coeff = -*coeff;
+        }
+    }
+
+    // Barrett reduction of coefficients below 2^31 - 2^22 in absolute value to [-6283009, 6283008], within (-Q, Q)
+    pub fn reduce(&mut self) {
+        for coeff in self.coeffs.iter_mut() {
+            debug_assert!(coeff.unsigned_abs() < (1 << 31) - (1 << 22));
+            *coeff = reduce32(*coeff);
         }
+    }
+
+    // a % Q of coefficients in (-2Q, 2Q), the representative in (-Q, Q) with the sign of a
+    pub fn mod_q(&mut self) {
+        for coeff in self.coeffs.iter_mut() {
+            debug_assert!(coeff.abs() < 2 * Q);
+            *coeff = mod_q(*coeff);
+        }
+    }
+
+    // the sum reduced with mod_q, a and b in (-Q, Q)
+    pub fn add(&self, b: &Poly) -> Poly {
+        let mut c = self.copy();
+        c.add_assign(b);
+        c.mod_q();
         c
     }
 
+    // the difference reduced with mod_q, a and b in (-Q, Q)
     pub fn sub(&self, b: &Poly) -> Poly {
-        let mut c: Poly = Poly::new();
-        let mut i: usize = 0;
-        loop {
-            c.coeffs[i] = (self.coeffs[i] - b.coeffs[i]) % Q;
-            i += 1;
-            if i == 256 {
-                break;
-            }
-        }
+        let mut c = self.copy();
+        c.sub_assign(b);
+        c.mod_q();
         c
     }
 
     // negation
     pub fn neg(&self) -> Poly {
-        let mut c: Poly = Poly::new();
-        let mut i: usize = 0;
-        loop {
-            c.coeffs[i] = -self.coeffs[i];
-            i += 1;
-            if i == 256 {
-                break;
-            }
-        }
+        let mut c = self.copy();
+        c.neg_assign();
         c
     }
 
@@ -78,7 +142,7 @@ impl Poly {
     pub fn lshift(&mut self, d: i32) {
         let mut i: usize = 0;
         loop {
-            self.coeffs[i] = self.coeffs[i] << d;
+            self.coeffs[i] <<= d;
             i += 1;
             if i == 256 {
                 break;
@@ -102,10 +166,29 @@ impl Poly {
 
 }
 
+// the NTT, the inverse NTT and caddq run on AVX2 when the CPU has it, the scalar versions give identical outputs
 impl Poly {
     // perform forward ntt
     pub fn ntt(&self) -> Poly {
         let mut c = self.copy();
+        c.ntt_in_place();
+        c
+    }
+
+    // forward ntt in place, coefficients in (-Q, Q) give coefficients in (-9Q, 9Q), each of the 8 layers adds
+    // a product in (-Q, Q)
+    pub fn ntt_in_place(&mut self) {
+        #[cfg(target_arch = "x86_64")]
+        if avx2::available() {
+            // the CPU has AVX2
+            unsafe { avx2::ntt(&mut self.coeffs) };
+            return;
+        }
+        self.ntt_scalar()
+    }
+
+    pub(crate) fn ntt_scalar(&mut self) {
+        let c = self;
         let mut k: usize = 0;
         let mut len: usize = 128;
         loop {
@@ -118,7 +201,7 @@ impl Poly {
                     let tiwddle: i32 =
                         montgomery_reduce(zeta as i64 * c.coeffs[start + i + len] as i64); // mont(c * zR) = cz mod Q
                     c.coeffs[start + len + i] = c.coeffs[start+i] - tiwddle;
-                    c.coeffs[start + i] = c.coeffs[start+i] + tiwddle;
+                    c.coeffs[start + i] += tiwddle;
                     i += 1;
                     if i == len {
                         break;
@@ -134,12 +217,30 @@ impl Poly {
                 break;
             }
         }
-        c 
     }
 
     // perform inverse ntt
     pub fn intt(&self) -> Poly {
         let mut c = self.copy();
+        c.intt_in_place();
+        c
+    }
+
+    // inverse ntt in place, the sums of the 8 layers grow to 256 times the input so coefficients in (-Q, Q),
+    // e.g. after reduce or mod_q, keep them below 2^31, the output is in (-Q, Q)
+    pub fn intt_in_place(&mut self) {
+        debug_assert!(self.coeffs.iter().all(|c| c.abs() < Q));
+        #[cfg(target_arch = "x86_64")]
+        if avx2::available() {
+            // the CPU has AVX2
+            unsafe { avx2::intt(&mut self.coeffs) };
+            return;
+        }
+        self.intt_scalar()
+    }
+
+    pub(crate) fn intt_scalar(&mut self) {
+        let c = self;
         let mut k: usize = 255;
         let mut len: usize = 1;
         loop {
@@ -151,7 +252,7 @@ impl Poly {
                 let mut i: usize = 0;
                 loop {
                     let temp: i32 = c.coeffs[start + i];
-                    c.coeffs[start + i] = c.coeffs[start + i] + c.coeffs[start + i + len];
+                    c.coeffs[start + i] += c.coeffs[start + i + len];
                     c.coeffs[start + i + len] =
                         montgomery_reduce((temp - c.coeffs[start + i + len]) as i64 * zeta as i64);
                     i += 1;
@@ -172,12 +273,21 @@ impl Poly {
         for coeff in c.coeffs.iter_mut() {
             *coeff = montgomery_reduce(*coeff as i64 * F as i64)
         } // eliminate the R^{-1} introduced by point-wise multiplication
-        c
     }
 
 
     // Add Q if negative, in place
-    pub fn caddq(&mut self) -> () {
+    pub fn caddq(&mut self) {
+        #[cfg(target_arch = "x86_64")]
+        if avx2::available() {
+            // the CPU has AVX2
+            unsafe { avx2::caddq(&mut self.coeffs) };
+            return;
+        }
+        self.caddq_scalar()
+    }
+
+    pub(crate) fn caddq_scalar(&mut self) {
         for coeff in self.coeffs.iter_mut() {
             *coeff += (*coeff >> 31) & Q;
         }
@@ -203,19 +313,77 @@ impl Poly {
         t
     }
 
-    // infinite norm
+    // infinite norm of coefficients in (-Q, Q), constant time
     pub fn inf_norm(&self) -> i32 {
         let mut max: i32 = 0;
         for coeff in self.coeffs.iter() {
-            let abs: i32 = (*coeff >> 31) ^ *coeff;
-            if abs > max {
-                max = abs;
-            }
+            // |a| = a - 2a if a is negative
+            let abs: i32 = *coeff - ((*coeff >> 31) & (2 * *coeff));
+            // max = abs if max < abs
+            max ^= (max ^ abs) & ((max - abs) >> 31);
         }
         max
     }
 }
 
+// a polynomial with at most 64 coefficients -1 or 1 and the others 0, as the challenge c of sample_in_ball,
+// kept as the positions and signs of the nonzero coefficients
+// c is public, so the multiplication may depend on the positions
+#[derive(Debug, Clone)]
+pub struct SparseTernary {
+    pos: [u8; 64],
+    neg: [bool; 64],
+    len: usize,
+}
+
+impl SparseTernary {
+    // panics if c has a coefficient other than -1, 0 and 1, or more than 64 nonzero coefficients
+    pub fn new(c: &Poly) -> SparseTernary {
+        let mut sparse = SparseTernary { pos: [0; 64], neg: [false; 64], len: 0 };
+        for (i, &coeff) in c.coeffs.iter().enumerate() {
+            if coeff != 0 {
+                assert!(coeff.abs() == 1 && sparse.len < 64, "not a sparse ternary polynomial");
+                sparse.pos[sparse.len] = i as u8;
+                sparse.neg[sparse.len] = coeff < 0;
+                sparse.len += 1;
+            }
+        }
+        sparse
+    }
+}
+
+impl Poly {
+    // self += c * a in Z[X]/(X^256 + 1) without the NTT, the coefficients of c * a are exact sums of at most
+    // 64 coefficients of a, e.g. |c * t0| <= 60 * 2^12 for the challenge of level 5
+    pub fn sparse_mul_acc(&mut self, c: &SparseTernary, a: &Poly) {
+        #[cfg(target_arch = "x86_64")]
+        if avx2::available() {
+            // the CPU has AVX2
+            unsafe { avx2::sparse_mul_acc(self, c, a) };
+            return;
+        }
+        self.sparse_mul_acc_scalar(c, a)
+    }
+
+    // the compiler vectorizes the loops, with AVX2 when avx2::sparse_mul_acc inlines this
+    #[inline(always)]
+    pub(crate) fn sparse_mul_acc_scalar(&mut self, c: &SparseTernary, a: &Poly) {
+        for k in 0..c.len {
+            // X^pos * a, the coefficients pushed past X^255 come back negated
+            let pos = c.pos[k] as usize;
+            let (low, high) = a.coeffs.split_at(256 - pos);
+            let (out_low, out_high) = self.coeffs.split_at_mut(pos);
+            if c.neg[k] {
+                out_high.iter_mut().zip(low).for_each(|(o, x)| *o -= x);
+                out_low.iter_mut().zip(high).for_each(|(o, x)| *o += x);
+            } else {
+                out_high.iter_mut().zip(low).for_each(|(o, x)| *o += x);
+                out_low.iter_mut().zip(high).for_each(|(o, x)| *o -= x);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -228,10 +396,34 @@ mod tests {
         assert_eq!(c.coeffs, [3; 256]);
     }
 
+    #[test]
+    fn debug_hides_coeffs_test() {
+        let a : Poly = Poly { coeffs: [1234567; 256] };
+        assert!(!format!("{:?}", a).contains("1234567"));
+        let v = crate::polyvec::polyvec::PolyVec { vec: [a.copy(), a] };
+        assert!(!format!("{:?}", v).contains("1234567"));
+    }
+
+    #[test]
+    fn inf_norm_test() {
+        let mut a = Poly::new();
+        assert_eq!(a.inf_norm(), 0);
+        a.coeffs[3] = 17;
+        a.coeffs[100] = -18;
+        assert_eq!(a.inf_norm(), 18);
+        a.coeffs[255] = Q - 1;
+        assert_eq!(a.inf_norm(), Q - 1);
+        a.coeffs[0] = -(Q - 1);
+        a.coeffs[255] = 0;
+        assert_eq!(a.inf_norm(), Q - 1);
+    }
+
     #[test]
     fn ntt_base_test() {
         let a : Poly = Poly { coeffs: [1; 256] };
-        let a_hat = a.ntt();
+        let mut a_hat = a.ntt();
+        // intt takes coefficients in (-Q, Q)
+        a_hat.reduce();
         let mut ap = a_hat.intt();
         for coeff in ap.coeffs.iter_mut() {
             *coeff = montgomery_reduce(*coeff as i64);
@@ -268,6 +460,46 @@ mod tests {
         assert_eq!(ress.coeffs, e.coeffs);
     }
 
+    #[test]
+    fn sparse_mul_test() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(25);
+        for tau in [1, 39, 49, 60, 64] {
+            let mut c = Poly::new();
+            let mut nonzero = 0;
+            while nonzero < tau {
+                let i = rng.gen_range(0..256);
+                if c.coeffs[i] == 0 {
+                    c.coeffs[i] = if rng.gen() { 1 } else { -1 };
+                    nonzero += 1;
+                }
+            }
+            let mut a = Poly::new();
+            a.coeffs.iter_mut().for_each(|x| *x = rng.gen_range(-(1 << 12) + 1..=1 << 12));
+
+            // schoolbook multiplication modulo X^256 + 1
+            let mut e = Poly::new();
+            for i in 0..256 {
+                for j in 0..256 {
+                    if i + j < 256 {
+                        e.coeffs[i + j] += c.coeffs[i] * a.coeffs[j];
+                    } else {
+                        e.coeffs[i + j - 256] -= c.coeffs[i] * a.coeffs[j];
+                    }
+                }
+            }
+            let mut sparse = Poly::new();
+            sparse.sparse_mul_acc(&SparseTernary::new(&c), &a);
+            assert_eq!(sparse.coeffs, e.coeffs);
+
+            // the inverse NTT gives the same exact product
+            let mut ntt = Poly::new();
+            ntt.point_wise_mul_acc(&c.ntt(), &a.ntt());
+            ntt.intt_in_place();
+            assert_eq!(ntt.coeffs, e.coeffs);
+        }
+    }
+
     // test for Poly::poly_add_test()
     #[test]
     fn poly_add_test_2() {
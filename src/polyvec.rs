@@ -1,23 +1,43 @@
 
+#[allow(clippy::module_inception)]
 pub mod polyvec {
     use crate::poly::Poly;
+    use zeroize::{Zeroize, ZeroizeOnDrop};
 
-    // a struct consists sevaral polynomials
-    #[derive(Debug)]
-    pub struct PolyVec {
-        pub vec: Vec<Poly>,
-        pub len: usize,
+    // a struct consists sevaral polynomials, k or l of them
+    // the polynomials wipe themselves when dropped
+    #[derive(Clone)]
+    pub struct PolyVec<const N: usize> {
+        pub vec: [Poly; N],
     }
 
-    impl PolyVec {
-        pub fn new(len: usize) -> PolyVec {
-            let mut vec = Vec::new();
-            for _ in 0..len {
-                vec.push(Poly::new());
-            }
-            PolyVec { vec, len }
+    // the coefficients are not printed
+    impl<const N: usize> core::fmt::Debug for PolyVec<N> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("PolyVec").field("len", &N).finish_non_exhaustive()
+        }
+    }
+
+    impl<const N: usize> Zeroize for PolyVec<N> {
+        fn zeroize(&mut self) {
+            self.vec.iter_mut().for_each(Poly::zeroize);
+        }
+    }
+
+    impl<const N: usize> ZeroizeOnDrop for PolyVec<N> {}
+
+    impl<const N: usize> Default for PolyVec<N> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<const N: usize> PolyVec<N> {
+        pub fn new() -> PolyVec<N> {
+            PolyVec { vec: core::array::from_fn(|_| Poly::new()) }
         }
 
+        #[cfg(test)]
         pub fn get(&self, i: usize) -> &Poly {
             &self.vec[i]
         }
@@ -26,98 +46,95 @@ pub mod polyvec {
             self.vec[i] = poly;
         }
 
-        pub fn add(&mut self, pv: &PolyVec) -> PolyVec {
-            let mut s = self.copy();
-            for i in 0..self.len {
-                s.vec[i] = s.vec[i].add(pv.get(i));
+        // the in-place versions reduce lazily with the bounds of the Poly methods they call
+        pub fn add_assign(&mut self, pv: &PolyVec<N>) {
+            for i in 0..N {
+                self.vec[i].add_assign(&pv.vec[i]);
             }
-            return s
         }
 
-        // negation
-        pub fn neg(&mut self) -> PolyVec {
-            let mut n = self.copy();
-            for i in 0..self.len {
-                n.vec[i] = n.vec[i].neg();
+        pub fn reduce(&mut self) {
+            for i in 0..N {
+                self.vec[i].reduce();
             }
-            n
         }
 
-        pub fn ntt(&self) ->PolyVec {
-            let mut pv = PolyVec::new(self.len);
-            for i in 0..self.len {
-                pv.vec[i] = self.vec[i].ntt();
+        pub fn mod_q(&mut self) {
+            for i in 0..N {
+                self.vec[i].mod_q();
             }
-            pv
         }
 
-        pub fn intt(&self) -> PolyVec{
-            let mut pv = PolyVec::new(self.len);
-            for i in 0..self.len {
-                pv.vec[i] = self.vec[i].intt();
+        pub fn ntt_in_place(&mut self) {
+            for i in 0..N {
+                self.vec[i].ntt_in_place();
+            }
+        }
+
+        pub fn intt_in_place(&mut self) {
+            for i in 0..N {
+                self.vec[i].intt_in_place();
             }
-            pv
         }
 
-        pub fn pointwise_acc(&mut self, b: &PolyVec) -> Poly {
-            let mut acc = Poly::new();
-            for i in 0..self.len {
-                acc = acc.add(&self.vec[i].point_wise_mul(&b.vec[i]));
+        // out = the sum of self[i] * b[i] in the NTT domain, Barrett reduced to (-Q, Q)
+        // the N products in (-Q, Q) are summed lazily, so N < 256
+        #[cfg(feature = "alloc")]
+        pub fn pointwise_acc_into(&self, b: &PolyVec<N>, out: &mut Poly) {
+            debug_assert!(N < 256);
+            out.coeffs = [0; 256];
+            for i in 0..N {
+                out.point_wise_mul_acc(&self.vec[i], &b.vec[i]);
             }
-            acc
+            out.reduce();
         }
 
         // convert to [0, q) in place]
         pub fn caddq(&mut self) {
-            for i in 0..self.len {
+            for i in 0..N {
                 self.vec[i].caddq();
             }
         }
 
         // return a copy of this instance
-        pub fn copy(&self) -> PolyVec {
-            let mut pv = PolyVec::new(self.len);
-            for i in 0..self.len {
-                pv.set(i, self.vec[i]);
+        pub fn copy(&self) -> PolyVec<N> {
+            let mut pv = PolyVec::new();
+            for i in 0..N {
+                pv.set(i, self.vec[i].copy());
             }
             pv
         }
-        
-        pub fn high_bits(&self, gamma2: i32) -> PolyVec {
-            let mut t = PolyVec::new(self.len);
-            for i in 0..self.len {
-                t.set(i, self.vec[i].high_bits(gamma2));
-            }
-            t
-        }
-
-        pub fn low_bits(&self, gamma2: i32) -> PolyVec {
-            let mut t = PolyVec::new(self.len);
-            for i in 0..self.len {
-                t.set(i, self.vec[i].low_bits(gamma2));
-            }
-            t
-        }
 
         //infinite norm
+        // constant time
         pub fn inf_norm(&self) -> i32 {
             let mut max = 0;
-            for i in 0..self.len {
+            for i in 0..N {
                 let norm = self.vec[i].inf_norm();
-                if norm > max {
-                    max = norm;
-                }
+                max ^= (max ^ norm) & ((max - norm) >> 31);
             }
             max
         }
 
         // every poly is left shifted by d bits
+        #[cfg(feature = "alloc")]
         pub fn left_shift(&mut self, d: i32) {
-            for i in 0..self.len {
+            for i in 0..N {
                 self.vec[i].lshift(d);
             }
         }
+    }
+
+    // A has k rows of l polynomials, held as a whole only by the prepared keys
+    #[cfg(feature = "alloc")]
+    pub type Matrix<const K: usize, const L: usize> = [PolyVec<L>; K];
 
+    // w = A * v for a matrix and a vector in NTT form, reduced to (-Q, Q)
+    #[cfg(feature = "alloc")]
+    pub fn matrix_mul_into<const K: usize, const L: usize>(A: &Matrix<K, L>, v: &PolyVec<L>, w: &mut PolyVec<K>) {
+        for i in 0..K {
+            A[i].pointwise_acc_into(v, &mut w.vec[i]);
+        }
     }
 
     #[cfg(test)]
@@ -125,13 +142,13 @@ pub mod polyvec {
         use super::*;
         #[test]
         fn polyvec_new_test() {
-            let a = PolyVec::new(10);
-            assert_eq!(a.len, 10);
+            let a = PolyVec::<10>::new();
+            assert_eq!(a.vec.len(), 10);
         }
 
         #[test]
         fn polyvec_get_test() {
-            let mut a = PolyVec::new(10);
+            let mut a = PolyVec::<10>::new();
             let b = Poly::new();
             a.set(0, b);
             assert_eq!(a.get(0).coeffs, [0; 256]);
@@ -139,7 +156,7 @@ pub mod polyvec {
 
         // #[test]
         // fn polyvec_get_mut_test() {
-        //     let mut a = PolyVec::new(10);
+        //     let mut a = PolyVec::<10>::new();
         //     let b = Poly::new();
         //     a.set(0, b);
         //     a.get_mut(0).coeffs[0] = 1;
@@ -148,7 +165,7 @@ pub mod polyvec {
 
         #[test]
         fn polyvec_set_test() {
-            let mut a = PolyVec::new(10);
+            let mut a = PolyVec::<10>::new();
             let b = Poly::new();
             a.set(0, b);
             assert_eq!(a.get(0).coeffs, [0; 256]);
@@ -156,7 +173,7 @@ pub mod polyvec {
 
         #[test]
         fn polyvec_pointwise_acc_test() {
-            let mut a = PolyVec::new(10);
+            let mut a = PolyVec::<10>::new();
             let b = Poly::new();
             a.set(0, b);
             // a.pointwise_acc(&a);
@@ -165,7 +182,7 @@ pub mod polyvec {
 
         #[test]
         fn polyvec_pointwise_acc_invmontgomery_test() {
-            let mut a = PolyVec::new(10);
+            let mut a = PolyVec::<10>::new();
             let b = Poly::new();
             a.set(0, b);
             // a.pointwise_acc_invmontgomery(&a);
@@ -6,23 +6,53 @@ pub fn montgomery_reduce(a: i64) -> i32 {
     t as i32
 }
 
+// a % Q for |a| < 2Q, computed with masks instead of a division whose time depends on a on some CPUs
+// as with %, the result has the sign of a
+pub fn mod_q(a: i32) -> i32 {
+    let a = a - (Q & ((Q - 1 - a) >> 31));
+    a + (Q & ((a + Q - 1) >> 31))
+}
+
+// Barrett reduction, a representative of a mod Q in [-6283009, 6283008] for a <= 2^31 - 2^22 - 1,
+// with a shift instead of a division
+pub fn reduce32(a: i32) -> i32 {
+    let t = (a + (1 << 22)) >> 23;
+    a - t * Q
+}
+
 
 
 #[cfg(test)]
 mod test {
+    use super::{mod_q, montgomery_reduce, reduce32};
     use crate::params::Q;
 
-    use super::montgomery_reduce;
-
     #[test]
     fn mont_reduce_test() {
         let mut a: i64 = -12232142;
-        let mut b: i64 = a << 32 % Q;
+        let mut b: i64 = a << 32;
         assert_eq!(montgomery_reduce(b), a as i32, "mont reduce: basic correct");
         a = -12314545;
-        b = a << 32 % Q;
+        b = a << 32;
         assert_eq!(montgomery_reduce(b), a as i32);
 
         assert_eq!(montgomery_reduce(-518909*3572224), -853297);
     }
+
+    #[test]
+    fn mod_q_test() {
+        for a in [0, 1, -1, Q - 1, Q, Q + 1, -Q + 1, -Q, -Q - 1, 2 * Q - 1, -2 * Q + 1, 12345678, -12345678] {
+            assert_eq!(mod_q(a), a % Q, "mod_q({})", a);
+        }
+    }
+
+    #[test]
+    fn reduce32_test() {
+        let max = i32::MAX - (1 << 22);
+        for a in [0, 1, -1, Q, -Q, 4 * Q + 5, -7 * Q - 3, max, -255 * (1 << 23) - (1 << 22), i32::MIN, 123456789, -123456789] {
+            let r = reduce32(a);
+            assert_eq!((r - a) % Q, 0, "reduce32({})", a);
+            assert!((-6283009..=6283008).contains(&r), "reduce32({}) = {}", a, r);
+        }
+    }
 }
\ No newline at end of file
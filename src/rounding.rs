@@ -1,15 +1,15 @@
-use crate::{polyvec::polyvec::PolyVec, params::Q};
+use crate::{poly::Poly, polyvec::polyvec::PolyVec, params::Q};
 
 
 // a mod^+ Q = a1*2^D + a0 with -2^{D-1} < a0 <= 2^{D-1}.
-pub fn power_2_round_q(t: PolyVec, d: u8) -> (PolyVec, PolyVec) {
-    let mut t1 = PolyVec::new(t.vec.len());
-    let mut t0 = PolyVec::new(t.vec.len());
+// t1 and t0 are written into the caller's vectors
+pub fn power_2_round_q<const N: usize>(t: &PolyVec<N>, d: u8, t1: &mut PolyVec<N>, t0: &mut PolyVec<N>) {
     for i in 0..t.vec.len() {
         for j in 0..t.vec[i].coeffs.len() {
             // t0.vec[i].coeffs[j] = t.vec[i].coeffs[j] & ((1 << d)-1);
             // t1.vec[i].coeffs[j] = t.vec[i].coeffs[j] >> d;
-            t1.vec[i].coeffs[j] = (t.vec[i].coeffs[j] + (1 << (d - 1))) >> d;
+            // -1 keeps t0 in (-2^{d-1}, 2^{d-1}] when the low d bits are exactly 2^{d-1}
+            t1.vec[i].coeffs[j] = (t.vec[i].coeffs[j] + (1 << (d - 1)) - 1) >> d;
             t0.vec[i].coeffs[j] = t.vec[i].coeffs[j] - (t1.vec[i].coeffs[j] << d);
             // 这地方为啥这么实现没太明白
             // 比如第d位是0，那么说明低d位小于2^{d-1}，直接留下即可
@@ -17,7 +17,6 @@ pub fn power_2_round_q(t: PolyVec, d: u8) -> (PolyVec, PolyVec) {
             // 自然，也就保证了t1 = (t-t0)/2^d
         }
     }
-    (t1, t0)
 }
 
 // maybe to avoid division?
@@ -35,7 +34,7 @@ fn decompose(r: i32, gamma2: i32) -> (i32, i32)
 
     let mut r0 = r - r1*2*gamma2;
     r0 -= (((Q-1)/2 - r0) >> 31) & Q;
-    return (r1, r0);
+    (r1, r0)
 }
 
 pub fn high_bits(r:i32, gamma2: i32) -> i32
@@ -50,22 +49,32 @@ pub fn low_bits(r: i32, gamma2: i32) -> i32
     r0
 }
 
+// 1 if r > gamma2, r < -gamma2 or r = -gamma2 and z != 0, constant time
 pub fn make_hints(r: i32, z:i32, gamma2: i32) -> i32 {
-    if r > gamma2 || r < -gamma2 || (r == -gamma2 && z != 0) {return 1;}
-    else {return 0;}
+    let outside = ((gamma2 - r) >> 31) | ((r + gamma2) >> 31);
+    // 1 if r = -gamma2 and 1 if z != 0
+    let on_bound = (((r + gamma2) | -(r + gamma2)) >> 31) + 1;
+    let nonzero = ((z | -z) >> 31) & 1;
+    (outside & 1) | (on_bound & nonzero)
 }
 
-pub fn make_hints_pv(r: PolyVec, z: PolyVec, gamma2: i32) -> PolyVec {
-    let mut h = PolyVec::new(r.vec.len());
+#[cfg(all(test, feature = "std"))]
+pub fn make_hints_pv<const N: usize>(r: &PolyVec<N>, z: &PolyVec<N>, gamma2: i32) -> PolyVec<N> {
+    let mut h = PolyVec::new();
     for i in 0..r.vec.len() {
-        for j in 0..r.vec[i].coeffs.len() {
-            h.vec[i].coeffs[j] = make_hints(r.vec[i].coeffs[j], z.vec[i].coeffs[j], gamma2);
-        }
+        make_hints_poly(&r.vec[i], &z.vec[i], gamma2, &mut h.vec[i]);
     }
     h
 }
 
-pub fn count_h(h: &PolyVec) -> i32 {
+// the hints of one polynomial, written into h
+pub fn make_hints_poly(r: &Poly, z: &Poly, gamma2: i32, h: &mut Poly) {
+    for j in 0..r.coeffs.len() {
+        h.coeffs[j] = make_hints(r.coeffs[j], z.coeffs[j], gamma2);
+    }
+}
+
+pub fn count_h<const N: usize>(h: &PolyVec<N>) -> i32 {
     let mut s = 0;
     for i in 0..h.vec.len() {
         for j in 0..h.vec[i].coeffs.len() {
@@ -81,13 +90,13 @@ pub fn use_hints(h:i32, r:i32, gamma2: i32) -> i32 {
 
     if gamma2 == 95232 {
         if r0 > 0 {
-            return match r1 == 43 {
+            match r1 == 43 {
                 true => 0,
                 false => r1 + 1,
             }
         }
         else {
-            return match r1 == 0 {
+            match r1 == 0 {
                 true => 43,
                 false => r1 - 1,
             }
@@ -95,8 +104,8 @@ pub fn use_hints(h:i32, r:i32, gamma2: i32) -> i32 {
     } // Q-1 / 88
 
     else if gamma2 == 261888 {
-        if r0 > 0 {return (r1 + 1) & 15;}
-        else {return (r1 - 1)&15;}
+        if r0 > 0 {(r1 + 1) & 15}
+        else {(r1 - 1)&15}
     } // Q-1 / 32
 
     else {
@@ -104,13 +113,28 @@ pub fn use_hints(h:i32, r:i32, gamma2: i32) -> i32 {
     }
 }
 
-// use hints for every coeff in a PolyVec
-pub fn use_hints_pv(h: &PolyVec, r: &PolyVec, gamma2: i32) -> PolyVec {
-    let mut r1 = PolyVec::new(r.vec.len());
+// use hints for every coeff in a PolyVec, r is replaced by its corrected high bits r1
+pub fn use_hints_pv<const N: usize>(h: &PolyVec<N>, r: &mut PolyVec<N>, gamma2: i32) {
     for i in 0..r.vec.len() {
         for j in 0..r.vec[i].coeffs.len() {
-            r1.vec[i].coeffs[j] = use_hints(h.vec[i].coeffs[j], r.vec[i].coeffs[j], gamma2);
+            r.vec[i].coeffs[j] = use_hints(h.vec[i].coeffs[j], r.vec[i].coeffs[j], gamma2);
         }
     }
-    r1
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::make_hints;
+
+    #[test]
+    fn make_hints_test() {
+        for gamma2 in [95232, 261888] {
+            for r in (-2 * gamma2 - 3..=2 * gamma2 + 3).step_by(7).chain([-gamma2 - 1, -gamma2, -gamma2 + 1, gamma2, gamma2 + 1]) {
+                for z in [0, 1, -1, 7] {
+                    let expected = (r > gamma2 || r < -gamma2 || (r == -gamma2 && z != 0)) as i32;
+                    assert_eq!(make_hints(r, z, gamma2), expected, "r = {}, z = {}", r, z);
+                }
+            }
+        }
+    }
+}
@@ -1,42 +1,192 @@
+use crate::keccak::{ShakeX4, SHAKE128_RATE, SHAKE256_RATE};
 use crate::pack::unpack_y;
 use crate::params::Q;
 use crate::poly::Poly;
+#[cfg(feature = "alloc")]
+use crate::polyvec::polyvec::Matrix;
 use crate::polyvec::polyvec::PolyVec;
 use sha3::digest::{ExtendableOutput, Update, XofReader};
 use sha3::{Shake256, Shake128};
+use zeroize::{Zeroize, Zeroizing};
 
-pub fn expand_A(rho: [u8; 32], k:i32, l: i32) -> Vec<PolyVec> {
-    let mut A = Vec::new();
-    for _i in 0..k as usize{
-        A.push(PolyVec::new(l as usize));
-    }
-    for i in 0..k as usize {
-        for j in 0..l as usize {
-            A[i].vec[j] = crate::sample::reject_sample(rho, i as u8, j as u8);
+// the polynomials 0..n are sampled four at a time with sample_x4, the last n % 4 one at a time with sample
+// they are sampled into one buffer of four polynomials that put reads them from, which is wiped at the end
+fn sample_batched(
+    n: usize,
+    mut sample_x4: impl FnMut([usize; 4], &mut [Poly; 4]),
+    mut sample: impl FnMut(usize) -> Poly,
+    mut put: impl FnMut(usize, &Poly),
+) {
+    let mut polys: [Poly; 4] = core::array::from_fn(|_| Poly::new());
+    let mut k = 0;
+    while k + 4 <= n {
+        sample_x4(core::array::from_fn(|m| k + m), &mut polys);
+        for (m, p) in polys.iter().enumerate() {
+            put(k + m, p);
         }
+        k += 4;
+    }
+    for k in k..n {
+        polys[0] = sample(k);
+        put(k, &polys[0]);
     }
+}
+
+// the whole matrix, kept by the prepared keys that use it for many signatures
+#[cfg(feature = "alloc")]
+pub fn expand_A<const K: usize, const L: usize>(rho: [u8; 32]) -> Matrix<K, L> {
+    let mut A: Matrix<K, L> = core::array::from_fn(|_| PolyVec::new());
+    sample_batched(
+        K * L,
+        |k, polys| reject_sample_x4(rho, k.map(|k| ((k / L) as u8, (k % L) as u8)), polys),
+        |k| reject_sample(rho, (k / L) as u8, (k % L) as u8),
+        |k, p| A[k / L].vec[k % L].coeffs = p.coeffs,
+    );
     A
 }
 
+// w = A * v for v in NTT form, reduced to (-Q, Q) as matrix_mul_into does
+// the entries of A are sampled as they are needed, at most four at a time, and A is never held as a whole
+pub fn expand_A_mul_into<const K: usize, const L: usize>(rho: [u8; 32], v: &PolyVec<L>, w: &mut PolyVec<K>) {
+    w.zeroize();
+    sample_batched(
+        K * L,
+        |k, polys| reject_sample_x4(rho, k.map(|k| ((k / L) as u8, (k % L) as u8)), polys),
+        |k| reject_sample(rho, (k / L) as u8, (k % L) as u8),
+        |k, a| w.vec[k / L].point_wise_mul_acc(a, &v.vec[k % L]),
+    );
+    w.reduce();
+}
+
+// s1 takes the nonces 0..l and s2 the nonces l..l+k
+pub fn expand_s<const K: usize, const L: usize>(rhoprime: &[u8; 64], eta: u8, s1: &mut PolyVec<L>, s2: &mut PolyVec<K>) {
+    sample_batched(
+        K + L,
+        |k, polys| error_sample_x4(*rhoprime, k.map(|k| k as u8), eta, polys),
+        |k| error_sample(*rhoprime, k as u8, eta),
+        |k, p| match k < L {
+            true => s1.vec[k].coeffs = p.coeffs,
+            false => s2.vec[k - L].coeffs = p.coeffs,
+        },
+    );
+}
+
+// y takes the nonces nonce..nonce+l
+pub fn expand_y<const L: usize>(rhoprime: &[u8; 64], nonce: i32, gamma1: i32, y: &mut PolyVec<L>) {
+    sample_batched(
+        L,
+        |k, polys| expand_mask_x4(*rhoprime, nonce, k.map(|k| k as i32), gamma1, polys),
+        |k| expand_mask(*rhoprime, nonce, k as i32, gamma1),
+        |k, p| y.vec[k].coeffs = p.coeffs,
+    );
+}
+
+// the coefficients of buf in [0, q) go to p from the index ctr on, returns the new number of coefficients
+fn rej_uniform(p: &mut Poly, mut ctr: usize, buf: &[u8]) -> usize {
+    for b in buf.chunks_exact(3) {
+        if ctr == 256 {
+            break;
+        }
+        let t = ((b[0] as i32) | ((b[1] as i32) << 8) | ((b[2] as i32) << 16)) & 0x7FFFFF;
+        if t < Q {
+            p.coeffs[ctr] = t;
+            ctr += 1;
+        }
+    }
+    ctr
+}
+
+// as rej_uniform for the coefficients in [-eta, eta], see error_sample
+fn rej_eta(p: &mut Poly, mut ctr: usize, buf: &[u8], eta: u8) -> usize {
+    for &b in buf {
+        for t in [b & 0x0F, b >> 4] {
+            if eta == 4 && t < 9 && ctr < 256 {
+                p.coeffs[ctr] = eta as i32 - t as i32;
+                ctr += 1;
+            } else if eta == 2 && t < 15 && ctr < 256 {
+                p.coeffs[ctr] = eta as i32 - (t % 5) as i32;
+                ctr += 1;
+            }
+        }
+    }
+    ctr
+}
+
+// reject_sample for four entries (i, j) of A, into polys
+pub fn reject_sample_x4(seed: [u8; 32], entries: [(u8, u8); 4], polys: &mut [Poly; 4]) {
+    let inputs: [[u8; 34]; 4] = entries.map(|(i, j)| {
+        let mut input = [0u8; 34];
+        input[..32].copy_from_slice(&seed);
+        input[32..].copy_from_slice(&[j, i]);
+        input
+    });
+    let mut H = ShakeX4::<SHAKE128_RATE>::new(inputs.each_ref().map(|input| &input[..]));
+    let mut ctr = [0usize; 4];
+    let mut buf = [[0u8; SHAKE128_RATE]; 4];
+    while ctr.iter().any(|&c| c < 256) {
+        H.squeeze_blocks(buf.each_mut().map(|b| &mut b[..]));
+        for n in 0..4 {
+            ctr[n] = rej_uniform(&mut polys[n], ctr[n], &buf[n]);
+        }
+    }
+}
+
+// error_sample for four nonces into polys, the seed and the squeezed bytes are secret and wiped
+pub fn error_sample_x4(seed: [u8; 64], nonces: [u8; 4], eta: u8, polys: &mut [Poly; 4]) {
+    let inputs = Zeroizing::new(nonces.map(|nonce| {
+        let mut input = [0u8; 66];
+        input[..64].copy_from_slice(&seed);
+        input[64] = nonce;
+        input
+    }));
+    let mut H = ShakeX4::<SHAKE256_RATE>::new(inputs.each_ref().map(|input| &input[..]));
+    let mut ctr = [0usize; 4];
+    let mut buf = Zeroizing::new([[0u8; SHAKE256_RATE]; 4]);
+    while ctr.iter().any(|&c| c < 256) {
+        H.squeeze_blocks(buf.each_mut().map(|b| &mut b[..]));
+        for n in 0..4 {
+            ctr[n] = rej_eta(&mut polys[n], ctr[n], &buf[n], eta);
+        }
+    }
+}
+
+// expand_mask for four i into polys, the seed and the squeezed bytes are secret and wiped
+pub fn expand_mask_x4(rhoprime: [u8; 64], nonce: i32, i: [i32; 4], gamma1: i32, polys: &mut [Poly; 4]) {
+    let inputs = Zeroizing::new(i.map(|i| {
+        let mut input = [0u8; 66];
+        input[..64].copy_from_slice(&rhoprime);
+        input[64..].copy_from_slice(&[(nonce + i) as u8, ((nonce + i) >> 8) as u8]);
+        input
+    }));
+    let mut H = ShakeX4::<SHAKE256_RATE>::new(inputs.each_ref().map(|input| &input[..]));
+    // 5 blocks hold the 576 or 640 bytes of y
+    let mut buf = Zeroizing::new([[0u8; 5 * SHAKE256_RATE]; 4]);
+    H.squeeze_blocks(buf.each_mut().map(|b| &mut b[..]));
+    let polyz = if gamma1 == 1 << 17 {
+        576
+    } else if gamma1 == 1 << 19 {
+        640
+    } else {
+        panic!("gamma1 not supported");
+    };
+    for (p, b) in polys.iter_mut().zip(buf.iter()) {
+        *p = unpack_y(gamma1, &b[..polyz]);
+    }
+}
 
 // generate a polynomial with coefficients in Z_q
+// the XOF is squeezed a SHAKE128 block at a time, 168 bytes are 56 candidates of 3 bytes
 pub fn reject_sample(seed: [u8; 32], i: u8, j: u8) -> Poly {
     let mut p = Poly::new();
     let mut H = Shake128::default();
     H.update(&seed);
     H.update(&[j, i]);
     let mut reader = H.finalize_xof();
-    let mut buf = [0u8; 3];
-    for i in 0..256 {
-        loop {
-            reader.read(&mut buf);
-            let mut t = (buf[0] as i32) | ((buf[1] as i32) << 8) | ((buf[2] as i32) << 16);
-            t &= 0x7FFFFF;
-            if t < Q {
-                p.coeffs[i] = t;
-                break;
-            }
-        }
+    let mut buf = [0u8; SHAKE128_RATE];
+    let mut ctr = 0;
+    while ctr < 256 {
+        reader.read(&mut buf);
+        ctr = rej_uniform(&mut p, ctr, &buf);
     }
     p
 }
@@ -44,40 +194,18 @@ pub fn reject_sample(seed: [u8; 32], i: u8, j: u8) -> Poly {
 // byte = b0 || b1
 // eta == 4: if b0 or b1 < 9 accept return eta - b0 eta - b1
 // eta == 2: if b0 or b1 < 15 accept return eta - (b0 mod 5) eta - (b1 mod 5)
+// the XOF is squeezed a SHAKE256 block at a time, the block is secret and wiped
 pub fn error_sample(seed: [u8; 64], nonce: u8, eta: u8) -> Poly {
     let mut p = Poly::new();
     let mut H = Shake256::default();
     H.update(&seed);
     H.update(&[nonce, 0]);
     let mut reader = H.finalize_xof();
-    let mut buf = [0u8; 1];
-    let mut i: usize = 0;
-    loop {
-        reader.read(&mut buf);
-        let t0 = buf[0] & 0x0F;
-        let t1 = buf[0] >> 4;
-        if eta == 4 {
-            if t0 < 9 && i < 256 {
-                p.coeffs[i] = eta as i32 - t0 as i32;
-                i += 1;
-            }
-            if t1 < 9 && i < 256 {
-                p.coeffs[i] = eta as i32 - t1 as i32;
-                i += 1;
-            }
-        } else if eta == 2 {
-            if t0 < 15 && i < 256 {
-                p.coeffs[i] = eta as i32 - (t0 % 5) as i32;
-                i += 1;
-            }
-            if t1 < 15 && i < 256 {
-                p.coeffs[i] = eta as i32 - (t1 % 5) as i32;
-                i += 1;
-            }
-            if i == 256 {
-                break;
-            }
-        }
+    let mut buf = Zeroizing::new([0u8; SHAKE256_RATE]);
+    let mut ctr = 0;
+    while ctr < 256 {
+        reader.read(buf.as_mut());
+        ctr = rej_eta(&mut p, ctr, buf.as_ref(), eta);
     }
     p
 }
@@ -94,12 +222,12 @@ pub fn expand_mask(rhoprime: [u8; 64], nonce: i32, i: i32, gamma1: i32) -> Poly
     if gamma1 == 1 << 17 {
         let mut buf = [0u8; 576];
         reader.read(&mut buf);
-        y = unpack_y(gamma1, &(&buf).to_vec());
+        y = unpack_y(gamma1, &buf);
     }
     else if gamma1 == 1 << 19 {
         let mut buf = [0u8; 640];
         reader.read(&mut buf);
-        y = unpack_y(gamma1, &(&buf).to_vec());
+        y = unpack_y(gamma1, &buf);
     } 
     else {
         panic!("gamma1 not supported");
@@ -108,23 +236,30 @@ pub fn expand_mask(rhoprime: [u8; 64], nonce: i32, i: i32, gamma1: i32) -> Poly
 }
 
 // return a poly with \tau 1/-1's and 256-\tau 0's
-pub fn sample_in_ball(cp: [u8; 32], tau: i32) -> Poly {
+// cp is the whole c tilde, which is 32 bytes in dilithium and lambda/4 bytes in ML-DSA
+// the XOF is squeezed a SHAKE256 block at a time, the next block is only squeezed once pos reaches its end
+pub fn sample_in_ball(cp: &[u8], tau: i32) -> Poly {
     let mut c = Poly::new();
     let mut H = Shake256::default();
-    H.update(&cp);
+    H.update(cp);
     let mut reader = H.finalize_xof();
-    let mut buf1 = [0u8; 8];
-    let mut buf2 = [0u8; 1];
+    let mut buf = [0u8; SHAKE256_RATE];
+    reader.read(&mut buf);
     // the first 8 bytes are used to generate the \tau signs, the rest 64-\tau is discarded
-    reader.read(&mut buf1);
+    let signs = u64::from_le_bytes(buf[..8].try_into().unwrap());
+    let mut pos = 8;
     for i in 256-tau as usize..256 {
         let mut j = 257;
         while j > i {
-            reader.read(&mut buf2);
-            j = buf2[0] as usize;
+            if pos == SHAKE256_RATE {
+                reader.read(&mut buf);
+                pos = 0;
+            }
+            j = buf[pos] as usize;
+            pos += 1;
         }
         c.coeffs[i] = c.coeffs[j];
-        c.coeffs[j] = match (buf1[((i+tau as usize-256) / 8) as usize] >> (((i + tau as usize -256) % 8))) & 0x01 {
+        c.coeffs[j] = match (signs >> (i + tau as usize - 256)) & 0x01 {
             0 => 1,
             _ => -1,
         };
@@ -135,8 +270,11 @@ pub fn sample_in_ball(cp: [u8; 32], tau: i32) -> Poly {
 #[cfg(test)]
 mod test {
     use crate::poly;
+    use sha3::digest::{ExtendableOutput, Update, XofReader};
+    use sha3::Shake256;
 
-    use super::error_sample;
+    use super::{error_sample, error_sample_x4, expand_mask, expand_mask_x4, reject_sample, reject_sample_x4};
+    use crate::poly::Poly;
 
 
     #[test]
@@ -157,4 +295,89 @@ mod test {
 
         assert_eq!(p.coeffs, [-2, 0, -2, -1, 2, 2, 2, 0, -2, 1, 1, 2, 2, -1, 0, 1, 2, 2, 1, 1, 0, 0, -2, 1, 1, 1, 1, -1, -1, -1, -2, -1, 0, 2, -1, 1, 1, -2, -1, 1, -1, 1, 0, -2, 0, 2, 0, -2, 0, 2, 1, 0, -1, -1, 0, 2, 2, 1, 1, 2, -1, -1, 1, 1, 2, -2, 2, 1, 0, -1, 2, -2, 2, -1, -2, -2, -2, 2, -1, 2, 2, -1, 2, -2, 1, 1, -2, 0, 1, 2, 1, 2, 0, 0, -1, 1, 0, 0, -2, 0, 1, -1, -2, 2, -2, 2, -2, -2, -1, -2, -1, -1, -1, 0, 1, -2, 1, 2, 0, 2, 1, 1, -2, 2, 0, 2, -2, 1, 2, 2, -1, -1, -2, -2, 0, -2, -2, 2, 1, -1, 2, 1, -1, -1, 0, 0, -2, 1, -2, 2, 2, 0, 1, 2, 0, 0, 0, -2, 0, 1, 2, 1, -2, -1, -2, -2, -2, 1, 1, 1, 1, 1, -1, -1, 2, 1, 2, 2, -2, 2, -2, -2, 0, -2, 1, 2, -1, 0, 2, 1, 2, 1, -2, -2, -1, 0, 1, 2, -1, 0, -2, 1, 2, -1, 1, 1, -1, 2, 1, 1, -1, -2, -2, -2, 0, 1, 1, 1, -2, -2, 2, 2, 2, 1, 0, 1, 0, -1, 2, -1, -1, 2, 2, -1, 1, 1, -1, 2, 0, 0, -2, -1, -2, 0, -2, -1, 1, -2, -2, 1, -1, 2, 1, 1, 1, 0])
     }
+
+    // the batched samplers give the same polynomials as the single ones
+    #[test]
+    fn test_sample_x4() {
+        let rho = [3u8; 32];
+        let entries = [(0, 0), (0, 1), (5, 3), (7, 6)];
+        let mut polys: [Poly; 4] = core::array::from_fn(|_| Poly::new());
+        reject_sample_x4(rho, entries, &mut polys);
+        for ((i, j), p) in entries.into_iter().zip(polys.iter()) {
+            assert_eq!(p.coeffs, reject_sample(rho, i, j).coeffs);
+        }
+
+        let seed = [5u8; 64];
+        for eta in [2, 4] {
+            let nonces = [0, 1, 9, 14];
+            error_sample_x4(seed, nonces, eta, &mut polys);
+            for (nonce, p) in nonces.into_iter().zip(polys.iter()) {
+                assert_eq!(p.coeffs, error_sample(seed, nonce, eta).coeffs);
+            }
+        }
+
+        for gamma1 in [1 << 17, 1 << 19] {
+            let i = [0, 1, 2, 3];
+            expand_mask_x4(seed, 700, i, gamma1, &mut polys);
+            for (i, p) in i.into_iter().zip(polys.iter()) {
+                assert_eq!(p.coeffs, expand_mask(seed, 700, i, gamma1).coeffs);
+            }
+        }
+    }
+
+    // sampling the entries of A while multiplying gives the product with the whole matrix
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_expand_A_mul() {
+        use crate::polyvec::polyvec::{matrix_mul_into, PolyVec};
+        let rho = [9u8; 32];
+        let mut v = PolyVec::<5>::new();
+        for (i, p) in v.vec.iter_mut().enumerate() {
+            *p = error_sample([i as u8; 64], 0, 4);
+            p.ntt_in_place();
+        }
+        let (mut w, mut w_streamed) = (PolyVec::<6>::new(), PolyVec::<6>::new());
+        matrix_mul_into(&super::expand_A::<6, 5>(rho), &v, &mut w);
+        super::expand_A_mul_into(rho, &v, &mut w_streamed);
+        for (a, b) in w.vec.iter().zip(&w_streamed.vec) {
+            assert_eq!(a.coeffs, b.coeffs);
+        }
+    }
+
+    // the squeezing a byte at a time before the samplers read whole blocks
+    fn sample_in_ball_bytewise(cp: &[u8], tau: i32) -> poly::Poly {
+        let mut c = poly::Poly::new();
+        let mut H = Shake256::default();
+        H.update(cp);
+        let mut reader = H.finalize_xof();
+        let mut buf1 = [0u8; 8];
+        let mut buf2 = [0u8; 1];
+        reader.read(&mut buf1);
+        for i in 256 - tau as usize..256 {
+            let mut j = 257;
+            while j > i {
+                reader.read(&mut buf2);
+                j = buf2[0] as usize;
+            }
+            c.coeffs[i] = c.coeffs[j];
+            c.coeffs[j] = match (buf1[(i + tau as usize - 256) / 8] >> ((i + tau as usize - 256) % 8)) & 0x01 {
+                0 => 1,
+                _ => -1,
+            };
+        }
+        c
+    }
+
+    #[test]
+    fn test_sample_in_ball_blocks() {
+        // c tildes of the lengths and taus of the three parameter sets
+        for (k, tau) in [39, 49, 60].into_iter().enumerate() {
+            for n in 0..500u32 {
+                let cp: Vec<u8> = n.to_le_bytes().iter().cycle().take(32 + 16 * k).copied().collect();
+                let c = super::sample_in_ball(&cp, tau);
+                assert_eq!(c.coeffs, sample_in_ball_bytewise(&cp, tau).coeffs);
+                assert_eq!(c.coeffs.iter().filter(|&&x| x != 0).count(), tau as usize);
+            }
+        }
+    }
 }
\ No newline at end of file
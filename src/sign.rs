@@ -1,221 +1,1067 @@
 use crate::rounding::{
-    count_h, make_hints_pv, power_2_round_q,
+    count_h, make_hints_poly, power_2_round_q,
     use_hints_pv,
 };
 use crate::pack::{
-    pack_delta, pack_pk, pack_sk, pack_w1, unpack_delta, unpack_pk, unpack_sk, unpack_t1,
+    pack_hints, pack_pk, pack_sk, pack_w1, pack_z_poly, unpack_delta, unpack_eta_poly, unpack_pk, unpack_t0_poly,
+    unpack_t1_poly,
 };
-use crate::params::{d, get_params, get_params_sign};
+#[cfg(feature = "alloc")]
+use crate::pack::unpack_sk;
+use crate::error::{Error, VerifyError};
+use crate::params::{d, get_level, get_mode_params, get_sizes, with_parameter_set, Mode, ParameterSet};
+use crate::poly::{Poly, SparseTernary};
 use crate::polyvec::polyvec::PolyVec;
-use crate::sample::{expand_A, expand_mask, sample_in_ball};
+#[cfg(feature = "alloc")]
+use crate::polyvec::polyvec::{matrix_mul_into, Matrix};
+use crate::sample::{expand_A_mul_into, expand_s, expand_y, sample_in_ball};
+#[cfg(feature = "alloc")]
+use crate::sample::expand_A;
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use crate::utils::parallel_map;
+use crate::utils::{ct_eq, ArrayBuf};
+#[cfg(feature = "alloc")]
+use crate::utils::{pop_wiped, reserve_wiped};
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec, vec::Vec};
 use sha3::{
     digest::{ExtendableOutput, Update, XofReader},
     Shake256,
 };
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
-pub fn key_pair(seed: &[u8; 32], security_level: u8) -> (Vec<u8>, Vec<u8>) {
-    let (k, l, eta) = get_params(security_level);
+// the secret key is wiped when it is dropped
+#[cfg(feature = "alloc")]
+pub fn key_pair(seed: &[u8; 32], security_level: u8) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), Error> {
+    key_pair_internal(seed, security_level, Mode::Dilithium)
+}
+
+// key_pair into caller provided buffers of the sizes given by get_sizes, without alloc
+// A is sampled as it is multiplied and never held, the stack needs about 42 KB at level 2 and 64 KB at level 5
+pub fn key_pair_into(seed: &[u8; 32], security_level: u8, pk: &mut [u8], sk: &mut [u8]) -> Result<(), Error> {
+    key_pair_into_internal(seed, security_level, Mode::Dilithium, pk, sk)
+}
+
+// an unsupported level or an sk of the wrong length is an Error
+#[cfg(feature = "alloc")]
+pub fn sign(sk: &[u8], m: &[u8], security_level: u8) -> Result<Vec<u8>, Error> {
+    sign_internal(sk, &[], m, &[], security_level, Mode::Dilithium)
+}
+
+// sign into a caller provided buffer of the size of a signature, without alloc
+// the packed key is read in place and A is sampled again for every attempt, which takes about 46 KB of stack
+// at level 2 and 64 KB at level 5 but more time than signing with alloc
+pub fn sign_into(sk: &[u8], m: &[u8], security_level: u8, sig: &mut [u8]) -> Result<(), Error> {
+    sign_into_internal(sk, &[], m, &[], security_level, Mode::Dilithium, sig)
+}
+
+// the security level is inferred from the lengths of pk and delta, unknown lengths are rejected
+pub fn verify(delta: &[u8], pk: &[u8], m: &[u8]) -> bool {
+    verify_internal(delta, pk, &[], m, Mode::Dilithium)
+}
+
+// verify, reporting why a signature is rejected
+pub fn verify_detailed(delta: &[u8], pk: &[u8], m: &[u8]) -> Result<(), VerifyError> {
+    verify_internal_detailed(delta, pk, &[], m, Mode::Dilithium)
+}
+
+// verify many (delta, pk, m) triples on all cores, with the result of verify_detailed for each of them
+// A and NTT(t1 * 2^d) of a public key are expanded once for all triples sharing it
+#[cfg(feature = "std")]
+pub fn verify_batch(items: &[(&[u8], &[u8], &[u8])]) -> Vec<Result<(), VerifyError>> {
+    verify_batch_internal(items, &[], Mode::Dilithium)
+}
+
+// recompute the public key of a secret key, which also checks that the secret key is consistent:
+// s1 and s2 are in [-eta, eta], tr is H(pk) and t0 are the low bits of t = A*s1 + s2
+#[cfg(feature = "alloc")]
+pub fn public_key(sk: &[u8]) -> Result<Vec<u8>, Error> {
+    public_key_internal(sk, Mode::Dilithium)
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn public_key_internal(sk: &[u8], mode: Mode) -> Result<Vec<u8>, Error> {
+    let security_level = [2, 3, 5]
+        .into_iter()
+        .find(|&level| get_sizes(level, mode).1 == sk.len())
+        .ok_or(Error::InvalidLength(sk.len()))?;
+    with_parameter_set!(security_level, public_key_inner(sk, mode))
+}
+
+// sk is allocated with its exact size and never reallocated, so no partial copy of the key is freed
+#[cfg(feature = "alloc")]
+pub(crate) fn key_pair_internal(
+    seed: &[u8; 32],
+    security_level: u8,
+    mode: Mode,
+) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>), Error> {
+    check_level(security_level)?;
+    let (pkbytes, skbytes, _) = get_sizes(security_level, mode);
+    let mut pk = vec![0u8; pkbytes];
+    let mut sk = Zeroizing::new(vec![0u8; skbytes]);
+    with_parameter_set!(security_level, key_pair_inner(seed, mode, &mut pk, &mut sk));
+    Ok((pk, sk))
+}
+
+// the buffers are checked so that the functions without alloc never panic on them
+pub(crate) fn key_pair_into_internal(
+    seed: &[u8; 32],
+    security_level: u8,
+    mode: Mode,
+    pk: &mut [u8],
+    sk: &mut [u8],
+) -> Result<(), Error> {
+    check_level(security_level)?;
+    let (pkbytes, skbytes, _) = get_sizes(security_level, mode);
+    check_len(pk, pkbytes)?;
+    check_len(sk, skbytes)?;
+    with_parameter_set!(security_level, key_pair_inner(seed, mode, pk, sk));
+    Ok(())
+}
+
+// mu = H(tr || pre || m) and rhoprime = H(K || rnd || mu)
+// dilithium uses an empty pre and rnd, ML-DSA uses pre = 0 || |ctx| || ctx and a 32 bytes rnd
+// the level and sk are checked as in sign_into_internal, so an unknown level or a short sk is an error
+#[cfg(feature = "alloc")]
+pub(crate) fn sign_internal(
+    sk: &[u8],
+    pre: &[u8],
+    m: &[u8],
+    rnd: &[u8],
+    security_level: u8,
+    mode: Mode,
+) -> Result<Vec<u8>, Error> {
+    check_level(security_level)?;
+    check_len(sk, get_sizes(security_level, mode).1)?;
+    let mut H = mu_hasher(&sk[64..64 + tr_bytes(mode)], pre);
+    H.update(m);
+    sign_mu(sk, &finalize_mu(H), rnd, security_level, mode)
+}
+
+pub(crate) fn sign_into_internal(
+    sk: &[u8],
+    pre: &[u8],
+    m: &[u8],
+    rnd: &[u8],
+    security_level: u8,
+    mode: Mode,
+    sig: &mut [u8],
+) -> Result<(), Error> {
+    check_level(security_level)?;
+    let (_, skbytes, sigbytes) = get_sizes(security_level, mode);
+    check_len(sk, skbytes)?;
+    check_len(sig, sigbytes)?;
+    with_parameter_set!(security_level, sign_inner(sk, pre, m, rnd, mode, sig));
+    Ok(())
+}
+
+fn check_level(security_level: u8) -> Result<(), Error> {
+    match security_level {
+        2 | 3 | 5 => Ok(()),
+        _ => Err(Error::UnsupportedLevel(security_level)),
+    }
+}
+
+fn check_len(buf: &[u8], len: usize) -> Result<(), Error> {
+    match buf.len() == len {
+        true => Ok(()),
+        false => Err(Error::InvalidLength(buf.len())),
+    }
+}
+
+pub(crate) fn verify_internal(delta: &[u8], pk: &[u8], pre: &[u8], m: &[u8], mode: Mode) -> bool {
+    verify_internal_detailed(delta, pk, pre, m, mode).is_ok()
+}
+
+pub(crate) fn verify_internal_detailed(
+    delta: &[u8],
+    pk: &[u8],
+    pre: &[u8],
+    m: &[u8],
+    mode: Mode,
+) -> Result<(), VerifyError> {
+    let mut H = mu_hasher(&compute_tr(pk, mode), pre);
+    H.update(m);
+    verify_mu_detailed(delta, pk, &finalize_mu(H), mode)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn verify_batch_internal(
+    items: &[(&[u8], &[u8], &[u8])],
+    pre: &[u8],
+    mode: Mode,
+) -> Vec<Result<(), VerifyError>> {
+    // group the triples by public key
+    let mut groups: HashMap<&[u8], usize> = HashMap::new();
+    let mut pks = vec![];
+    let group: Vec<usize> = items
+        .iter()
+        .map(|&(_, pk, _)| {
+            *groups.entry(pk).or_insert_with(|| {
+                pks.push(pk);
+                pks.len() - 1
+            })
+        })
+        .collect();
+
+    // tr and the expanded key of every distinct public key, None if no parameter set has a key of its length
+    let prepared = parallel_map(&pks, |pk| {
+        let security_level = [2, 3, 5].into_iter().find(|&level| get_sizes(level, mode).0 == pk.len())?;
+        Some((compute_tr(pk, mode), prepare_pk(pk, security_level)))
+    });
+
+    let jobs: Vec<_> = items.iter().zip(group).collect();
+    parallel_map(&jobs, |&(&(delta, pk, m), group)| match &prepared[group] {
+        Some((tr, prepared_pk)) => {
+            let mut H = mu_hasher(tr, pre);
+            H.update(m);
+            verify_mu_prepared(delta, prepared_pk, &finalize_mu(H), mode)
+        }
+        None => Err(VerifyError::InvalidLength { pk: pk.len(), sig: delta.len() }),
+    })
+}
+
+// the rejection loop of sign on a precomputed mu
+// A is expanded on the heap once instead of being sampled again for every attempt as sign_into does
+#[cfg(feature = "alloc")]
+pub(crate) fn sign_mu(sk: &[u8], mu: &[u8; 64], rnd: &[u8], security_level: u8, mode: Mode) -> Result<Vec<u8>, Error> {
+    check_level(security_level)?;
+    check_len(sk, get_sizes(security_level, mode).1)?;
+    Ok(sign_mu_prepared(&prepare_sk(sk, security_level, mode), mu, rnd, mode))
+}
+
+pub(crate) fn verify_mu(delta: &[u8], pk: &[u8], mu: &[u8; 64], mode: Mode) -> bool {
+    verify_mu_detailed(delta, pk, mu, mode).is_ok()
+}
+
+// A, s1, s2, t0 and K of a secret key, the part of signing that does not depend on the message
+// s1, s2 and t0 are in NTT form unless the parameter set multiplies them with a sparse challenge
+// the polynomials and K are wiped when it is dropped
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub(crate) struct ExpandedSk<const K: usize, const L: usize> {
+    A: Matrix<K, L>,
+    s1: PolyVec<L>,
+    s2: PolyVec<K>,
+    t0: PolyVec<K>,
+    key: Zeroizing<[u8; 32]>,
+}
+
+// an expanded secret key of any parameter set
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub(crate) enum PreparedSk {
+    MlDsa44(Box<ExpandedSk<4, 4>>),
+    MlDsa65(Box<ExpandedSk<6, 5>>),
+    MlDsa87(Box<ExpandedSk<8, 7>>),
+}
+
+// sk must have the length of the secret key of the security level in the mode
+#[cfg(feature = "alloc")]
+pub(crate) fn prepare_sk(sk: &[u8], security_level: u8, mode: Mode) -> PreparedSk {
+    use crate::params::{MlDsa44, MlDsa65, MlDsa87};
+    match security_level {
+        2 => PreparedSk::MlDsa44(Box::new(expand_sk::<MlDsa44, 4, 4>(sk, mode))),
+        3 => PreparedSk::MlDsa65(Box::new(expand_sk::<MlDsa65, 6, 5>(sk, mode))),
+        5 => PreparedSk::MlDsa87(Box::new(expand_sk::<MlDsa87, 8, 7>(sk, mode))),
+        _ => panic!("security level not supported"),
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn sign_mu_prepared(sk: &PreparedSk, mu: &[u8; 64], rnd: &[u8], mode: Mode) -> Vec<u8> {
+    use crate::params::{MlDsa44, MlDsa65, MlDsa87};
+    match sk {
+        PreparedSk::MlDsa44(sk) => sign_expanded_vec::<MlDsa44, 4, 4>(sk, mu, rnd, mode),
+        PreparedSk::MlDsa65(sk) => sign_expanded_vec::<MlDsa65, 6, 5>(sk, mu, rnd, mode),
+        PreparedSk::MlDsa87(sk) => sign_expanded_vec::<MlDsa87, 8, 7>(sk, mu, rnd, mode),
+    }
+}
+
+// A and NTT(t1 * 2^d) of a public key, the part of verification that does not depend on the signature
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub(crate) struct ExpandedPk<const K: usize, const L: usize> {
+    A: Matrix<K, L>,
+    t1_hat: PolyVec<K>,
+}
+
+// an expanded public key of any parameter set, boxed because A has up to 56 polynomials
+#[cfg(feature = "alloc")]
+#[derive(Clone)]
+pub(crate) enum PreparedPk {
+    MlDsa44(Box<ExpandedPk<4, 4>>),
+    MlDsa65(Box<ExpandedPk<6, 5>>),
+    MlDsa87(Box<ExpandedPk<8, 7>>),
+}
+
+// pk must have the length of the public key of the security level
+#[cfg(feature = "alloc")]
+pub(crate) fn prepare_pk(pk: &[u8], security_level: u8) -> PreparedPk {
+    match security_level {
+        2 => PreparedPk::MlDsa44(Box::new(expand_pk(pk))),
+        3 => PreparedPk::MlDsa65(Box::new(expand_pk(pk))),
+        5 => PreparedPk::MlDsa87(Box::new(expand_pk(pk))),
+        _ => panic!("security level not supported"),
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn verify_mu_prepared(
+    delta: &[u8],
+    pk: &PreparedPk,
+    mu: &[u8; 64],
+    mode: Mode,
+) -> Result<(), VerifyError> {
+    use crate::params::{MlDsa44, MlDsa65, MlDsa87};
+    match pk {
+        PreparedPk::MlDsa44(pk) => verify_with::<MlDsa44, 4, 4>(delta, pk.as_ref(), mu, mode),
+        PreparedPk::MlDsa65(pk) => verify_with::<MlDsa65, 6, 5>(delta, pk.as_ref(), mu, mode),
+        PreparedPk::MlDsa87(pk) => verify_with::<MlDsa87, 8, 7>(delta, pk.as_ref(), mu, mode),
+    }
+}
+
+pub(crate) fn verify_mu_detailed(delta: &[u8], pk: &[u8], mu: &[u8; 64], mode: Mode) -> Result<(), VerifyError> {
+    match get_level(pk.len(), delta.len(), mode) {
+        Some(security_level) => with_parameter_set!(security_level, verify_mu_inner(delta, pk, mu, mode)),
+        None => Err(VerifyError::InvalidLength { pk: pk.len(), sig: delta.len() }),
+    }
+}
+
+// tr = H(pk), 32 bytes in dilithium and 64 bytes in ML-DSA
+pub(crate) fn compute_tr(pk: &[u8], mode: Mode) -> ArrayBuf<64> {
+    let mut H = Shake256::default();
+    H.update(pk);
+    let mut tr = ArrayBuf::zeroed(tr_bytes(mode));
+    H.finalize_xof().read(&mut tr);
+    tr
+}
+
+// mu = H(tr || pre || m), the message is absorbed into the returned hasher by the caller
+pub(crate) fn mu_hasher(tr: &[u8], pre: &[u8]) -> Shake256 {
+    let mut H = Shake256::default();
+    H.update(tr);
+    H.update(pre);
+    H
+}
+
+fn tr_bytes(mode: Mode) -> usize {
+    match mode {
+        Mode::Dilithium => 32,
+        Mode::MlDsa => 64,
+    }
+}
+
+pub(crate) fn finalize_mu(H: Shake256) -> [u8; 64] {
+    let mut mu = [0u8; 64];
+    H.finalize_xof().read(&mut mu);
+    mu
+}
+
+// a packed secret key can hold s1 and s2 coefficients outside of [-eta, eta], which key_pair never produces
+#[cfg(feature = "alloc")]
+pub(crate) fn check_sk(sk: &[u8], security_level: u8, mode: Mode) -> bool {
+    with_parameter_set!(security_level, check_sk_inner(sk, mode))
+}
+
+// the signature must be a strict encoding, see unpack_delta
+#[cfg(feature = "alloc")]
+pub(crate) fn check_signature(delta: &[u8], security_level: u8, mode: Mode) -> Result<(), Error> {
+    with_parameter_set!(security_level, check_signature_inner(delta, mode))
+}
+
+// K and L must be the dimensions of P, with_parameter_set takes care of it
+// pk and sk must have the sizes of the parameter set
+// not inlined into the match of with_parameter_set, whose frame would hold the vectors of level 5 at every level
+#[inline(never)]
+fn key_pair_inner<P: ParameterSet, const K: usize, const L: usize>(
+    seed: &[u8; 32],
+    mode: Mode,
+    pk: &mut [u8],
+    sk: &mut [u8],
+) {
+    const { assert!(P::K == K && P::L == L) };
 
     // use SHAKE256 to generaterho, rho' and K, whose length are 32, 64 and 32 bytes respectively
+    // ML-DSA binds the seed to the parameter set by absorbing k and l after it
     let mut H = Shake256::default();
     H.update(seed);
+    if mode == Mode::MlDsa {
+        H.update(&[K as u8, L as u8]);
+    }
     let mut reader = H.finalize_xof();
     let mut rho = [0u8; 32];
-    let mut rhoprime = [0u8; 64];
-    let mut K = [0u8; 32];
+    let mut rhoprime = Zeroizing::new([0u8; 64]);
+    let mut key = Zeroizing::new([0u8; 32]);
     reader.read(&mut rho);
-    reader.read(&mut rhoprime);
-    reader.read(&mut K);
+    reader.read(rhoprime.as_mut());
+    reader.read(key.as_mut());
+
+    // gen s1, s2, s1 takes nonces 0..l and s2 takes nonces l..l+k
+    let mut s1 = PolyVec::<L>::new();
+    let mut s2 = PolyVec::<K>::new();
+    expand_s::<K, L>(&rhoprime, P::ETA as u8, &mut s1, &mut s2);
+
+    // the k*l polynomials of A are sampled from rho as t is accumulated
+    let mut t1 = PolyVec::<K>::new();
+    let mut t0 = PolyVec::<K>::new();
+    compute_t(rho, &s1, &s2, &mut t1, &mut t0);
+
+    // pack pk
+    pack_pk(&t1, &rho, pk);
+
+    // get tr
+    let tr = compute_tr(pk, mode);
+
+    // pack sk
+    pack_sk(&rho, &key, &tr, &s1, &s2, &t0, P::ETA, sk);
+}
+
+// t = NTT^-1(A_hat * NTT(s1)) + s2 with A expanded from rho, split into t1 and t0
+fn compute_t<const K: usize, const L: usize>(
+    rho: [u8; 32],
+    s1: &PolyVec<L>,
+    s2: &PolyVec<K>,
+    t1: &mut PolyVec<K>,
+    t0: &mut PolyVec<K>,
+) {
+    let mut s1_hat = s1.copy();
+    s1_hat.ntt_in_place();
+    let mut t = PolyVec::new();
+    expand_A_mul_into(rho, &s1_hat, &mut t);
+    t.intt_in_place();
+    t.add_assign(s2);
+    t.mod_q();
+    t.caddq();
+    power_2_round_q(&t, d, t1, t0);
+}
+
+#[cfg(feature = "alloc")]
+fn public_key_inner<P: ParameterSet, const K: usize, const L: usize>(sk: &[u8], mode: Mode) -> Result<Vec<u8>, Error> {
+    const { assert!(P::K == K && P::L == L) };
+    let (trbytes, _) = get_mode_params::<P>(mode);
+    let (rho, _, tr, s1, s2, t0) = unpack_sk::<K, L>(sk, P::ETA, trbytes);
+    let in_range = s1
+        .vec
+        .iter()
+        .chain(s2.vec.iter())
+        .all(|p| p.coeffs.iter().all(|c| c.abs() <= P::ETA));
+    if !in_range {
+        return Err(Error::SecretOutOfRange);
+    }
+
+    let mut t1 = PolyVec::<K>::new();
+    let mut t0_computed = PolyVec::<K>::new();
+    compute_t(rho, &s1, &s2, &mut t1, &mut t0_computed);
+    let mut pk = vec![0u8; P::PK_BYTES];
+    pack_pk(&t1, &rho, &mut pk);
+    if compute_tr(&pk, mode)[..] != *tr {
+        return Err(Error::TrMismatch);
+    }
+    if t0.vec.iter().zip(t0_computed.vec.iter()).any(|(a, b)| a.coeffs != b.coeffs) {
+        return Err(Error::T0Mismatch);
+    }
+    Ok(pk)
+}
+
+fn sign_inner<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &[u8],
+    pre: &[u8],
+    m: &[u8],
+    rnd: &[u8],
+    mode: Mode,
+    delta: &mut [u8],
+) {
+    let (trbytes, _) = get_mode_params::<P>(mode);
+
+    // mu = H(tr || pre || m)
+    let mut H = mu_hasher(&sk[64..64 + trbytes], pre);
+    H.update(m);
+    sign_mu_inner::<P, K, L>(sk, &finalize_mu(H), rnd, mode, delta)
+}
+
+// signs straight from the packed key, see PackedSk
+// not inlined for the stack of the lower levels, see key_pair_inner
+#[inline(never)]
+fn sign_mu_inner<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &[u8],
+    mu: &[u8; 64],
+    rnd: &[u8],
+    mode: Mode,
+    delta: &mut [u8],
+) {
+    let (trbytes, _) = get_mode_params::<P>(mode);
+    sign_with::<P, K, L>(&PackedSk::<P> { sk, trbytes, params: PhantomData }, mu, rnd, mode, delta)
+}
+
+#[cfg(feature = "alloc")]
+fn expand_sk<P: ParameterSet, const K: usize, const L: usize>(sk: &[u8], mode: Mode) -> ExpandedSk<K, L> {
+    let (trbytes, _) = get_mode_params::<P>(mode);
+    let (rho, key, _, mut s1, mut s2, mut t0) = unpack_sk::<K, L>(sk, P::ETA, trbytes);
+    if !P::SPARSE_CHALLENGE {
+        s1.ntt_in_place();
+        s2.ntt_in_place();
+        t0.ntt_in_place();
+    }
 
     // use SHAKE256 to generate a random polynomial A (k*l polynomials)
-    let mut A = expand_A(rho, k, l);
+    let key = Zeroizing::new(key);
+    ExpandedSk { A: expand_A::<K, L>(rho), s1, s2, t0, key }
+}
 
-    let mut s1 = PolyVec::new(l as usize);
-    let mut s2 = PolyVec::new(k as usize);
+// A, s1, s2, t0 and K of a secret key as signing uses them, s1, s2 and t0 only multiplied with the challenge
+// one polynomial at a time, so a key that unpacks them on demand needs no room for the vectors
+trait SecretKeyParts<const K: usize, const L: usize> {
+    fn key(&self) -> &[u8; 32];
 
-    // gen s1, s2
-    for i in 0..(k + l) as usize {
-        if i < k as usize {
-            s1.vec[i] = crate::sample::error_sample(rhoprime, i as u8, eta as u8);
-        } else {
-            s2.vec[i - k as usize] = crate::sample::error_sample(rhoprime, i as u8, eta as u8);
-        }
+    // w = A * v for v in NTT form, reduced to (-Q, Q)
+    fn mul_A_into(&self, v: &PolyVec<L>, w: &mut PolyVec<K>);
+
+    // out = c * s1[i], c * s2[i] and c * t0[i]
+    fn mul_s1_into(&self, c: &Challenge, i: usize, out: &mut Poly);
+    fn mul_s2_into(&self, c: &Challenge, i: usize, out: &mut Poly);
+    fn mul_t0_into(&self, c: &Challenge, i: usize, out: &mut Poly);
+}
+
+#[cfg(feature = "alloc")]
+impl<const K: usize, const L: usize> SecretKeyParts<K, L> for ExpandedSk<K, L> {
+    fn key(&self) -> &[u8; 32] {
+        &self.key
     }
 
-    // calculate t = NTT^-1(A_hat * NTT(s1))+s2
-    let s1_hat = s1.ntt();
-    let mut t = PolyVec::new(l as usize);
-    for i in 0..k as usize {
-        t.vec[i] = A[i].pointwise_acc(&s1_hat).intt();
+    fn mul_A_into(&self, v: &PolyVec<L>, w: &mut PolyVec<K>) {
+        matrix_mul_into(&self.A, v, w);
     }
-    t = t.add(&s2);
 
-    // calculate t1 and t0
-    t.caddq();
-    let (t1, t0) = power_2_round_q(t, d);
+    fn mul_s1_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        c.mul_into(&self.s1.vec[i], out);
+    }
 
-    // pack pk
-    let pk = pack_pk(&t1, &rho);
+    fn mul_s2_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        c.mul_into(&self.s2.vec[i], out);
+    }
 
-    // get tr
-    H = Shake256::default();
-    H.update(&pk);
-    let mut reader = H.finalize_xof();
-    let mut tr = [0u8; 32];
-    reader.read(&mut tr);
+    fn mul_t0_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        c.mul_into(&self.t0.vec[i], out);
+    }
+}
 
-    // pack sk
-    let sk = pack_sk(&rho, &K, &tr, &s1, &s2, &t0, eta as i32);
+// a packed secret key of the parameter set P, signing without alloc reads it in place:
+// A is sampled from rho again for every commitment and s1, s2 and t0 are unpacked for every product with c,
+// which is slower than an expanded key but keeps the stack to a few vectors
+struct PackedSk<'a, P> {
+    sk: &'a [u8],
+    trbytes: usize,
+    params: PhantomData<P>,
+}
 
-    (pk, sk)
+impl<P: ParameterSet> PackedSk<'_, P> {
+    // out = c * a for the polynomial of s1 or s2 with index i of s1 || s2
+    fn mul_eta_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        let offset = 64 + self.trbytes + i * P::POLY_ETA_PACKED_BYTES;
+        let mut a = Poly::new();
+        unpack_eta_poly(P::ETA, &self.sk[offset..], &mut a);
+        c.mul_unpacked_into(&mut a, out);
+    }
 }
 
-pub fn sign(sk: &Vec<u8>, m: &Vec<u8>, security_level: u8) -> Vec<u8> {
-    let (k, l, eta, gamma1, gamma2, tau, omega) = get_params_sign(2);
+impl<P: ParameterSet, const K: usize, const L: usize> SecretKeyParts<K, L> for PackedSk<'_, P> {
+    fn key(&self) -> &[u8; 32] {
+        self.sk[32..64].try_into().unwrap()
+    }
 
-    let (rho, K, tr, mut s1, mut s2, mut t0) = unpack_sk(sk, eta, k, l);
+    fn mul_A_into(&self, v: &PolyVec<L>, w: &mut PolyVec<K>) {
+        expand_A_mul_into(self.sk[..32].try_into().unwrap(), v, w);
+    }
 
-    s1 = s1.ntt();
-    s2 = s2.ntt();
-    t0 = t0.ntt();
+    fn mul_s1_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        self.mul_eta_into(c, i, out);
+    }
 
-    // use SHAKE256 to generate a random polynomial A (k*l polynomials)
-    let mut A = expand_A(rho, k, l);
+    fn mul_s2_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        self.mul_eta_into(c, L + i, out);
+    }
 
-    let mut mu = [0u8; 64];
-    let mut rhoprime = [0u8; 64];
+    fn mul_t0_into(&self, c: &Challenge, i: usize, out: &mut Poly) {
+        let offset = 64 + self.trbytes + (K + L) * P::POLY_ETA_PACKED_BYTES + i * 416;
+        let mut a = Poly::new();
+        unpack_t0_poly(&self.sk[offset..], &mut a);
+        c.mul_unpacked_into(&mut a, out);
+    }
+}
+
+// delta must have the size of a signature of the parameter set in the mode
+fn sign_with<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &impl SecretKeyParts<K, L>,
+    mu: &[u8; 64],
+    rnd: &[u8],
+    mode: Mode,
+    delta: &mut [u8],
+) {
+    const { assert!(P::K == K && P::L == L) };
+    let rhoprime = derive_rhoprime(sk.key(), rnd, mu);
+    let mut commitment = Commitment::new();
+    let mut nonce = 0;
+    loop {
+        commit::<P, K, L>(sk, &rhoprime, nonce, &mut commitment);
+        nonce += L as i32;
+        if attempt::<P, K, L>(sk, &commitment, mu, mode, delta) {
+            return;
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn sign_expanded_vec<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &ExpandedSk<K, L>,
+    mu: &[u8; 64],
+    rnd: &[u8],
+    mode: Mode,
+) -> Vec<u8> {
+    let mut delta = vec![0u8; get_sizes(P::LEVEL, mode).2];
+    sign_with::<P, K, L>(sk, mu, rnd, mode, &mut delta);
+    delta
+}
 
-    // mu = H(tr || m)
+// rhoprime = H(K || rnd || mu), an empty mu for the commitments precomputed before the message is known
+fn derive_rhoprime(key: &[u8; 32], rnd: &[u8], mu: &[u8]) -> Zeroizing<[u8; 64]> {
+    let mut rhoprime = Zeroizing::new([0u8; 64]);
     let mut H = Shake256::default();
-    H.update(&tr);
-    H.update(&m);
+    H.update(key);
+    H.update(rnd);
+    H.update(mu);
     let mut reader = H.finalize_xof();
-    reader.read(&mut mu);
+    reader.read(rhoprime.as_mut());
+    rhoprime
+}
 
-    // rhoprime = H(K || mu)
-    H = Shake256::default();
-    H.update(&K);
-    H.update(&mu);
-    reader = H.finalize_xof();
-    reader.read(&mut rhoprime);
+// y, w = A*y and its high bits w1, the part of an iteration of the rejection loop that does not depend on the message
+// y reveals the secret key together with the z of a signature, so a commitment is wiped when it is dropped
+// and is not Clone, using it twice would reveal the secret key as well
+pub(crate) struct Commitment<const K: usize, const L: usize> {
+    y: PolyVec<L>,
+    w: PolyVec<K>,
+    w1: PolyVec<K>,
+}
 
-    let mut nonce = 0;
+impl<const K: usize, const L: usize> Commitment<K, L> {
+    fn new() -> Self {
+        Commitment { y: PolyVec::new(), w: PolyVec::new(), w1: PolyVec::new() }
+    }
+}
+
+impl<const K: usize, const L: usize> Zeroize for Commitment<K, L> {
+    fn zeroize(&mut self) {
+        self.y.zeroize();
+        self.w.zeroize();
+        self.w1.zeroize();
+    }
+}
 
-    let mut delta = vec![];
+impl<const K: usize, const L: usize> ZeroizeOnDrop for Commitment<K, L> {}
 
-    let mut pass = false;
+// the precomputed commitments of a signing key of any parameter set
+#[cfg(feature = "alloc")]
+pub(crate) enum Commitments {
+    MlDsa44(Vec<Commitment<4, 4>>),
+    MlDsa65(Vec<Commitment<6, 5>>),
+    MlDsa87(Vec<Commitment<8, 7>>),
+}
 
-    while !pass {
-        let mut z = PolyVec::new(l as usize);
-        let mut h = PolyVec::new(k as usize);
-        let mut y = PolyVec::new(l as usize);
-        for i in 0..l as usize {
-            y.set(i, expand_mask(rhoprime, nonce, i as i32, gamma1));
+#[cfg(feature = "alloc")]
+impl Commitments {
+    pub(crate) fn new(sk: &PreparedSk) -> Commitments {
+        match sk {
+            PreparedSk::MlDsa44(_) => Commitments::MlDsa44(Vec::new()),
+            PreparedSk::MlDsa65(_) => Commitments::MlDsa65(Vec::new()),
+            PreparedSk::MlDsa87(_) => Commitments::MlDsa87(Vec::new()),
         }
-        nonce += l;
-        let y_hat = y.ntt();
-        let mut w = PolyVec::new(k as usize);
-        for i in 0..k as usize {
-            w.vec[i] = A[i].pointwise_acc(&y_hat).intt();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Commitments::MlDsa44(pool) => pool.len(),
+            Commitments::MlDsa65(pool) => pool.len(),
+            Commitments::MlDsa87(pool) => pool.len(),
         }
-        w.caddq();
-        let w1 = w.high_bits(gamma2);
-        let w1_ba = pack_w1(&w1, gamma2, k);
-        H = Shake256::default();
-        H.update(&mu);
-        H.update(&w1_ba);
-        reader = H.finalize_xof();
-        let mut cp = [0u8; 32];
-        reader.read(&mut cp);
-        let c = sample_in_ball(cp, tau).ntt();
-
-        //  Compute z, reject if it reveals secret
-        for i in 0..l as usize {
-            z.vec[i] = c.point_wise_mul(&s1.vec[i]).intt();
-            z.vec[i] = z.vec[i].add(&y.vec[i]);
+    }
+
+    // room for n more commitments, growing the pool must not free a buffer holding copies of commitments
+    pub(crate) fn reserve(&mut self, n: usize) {
+        match self {
+            Commitments::MlDsa44(pool) => reserve_wiped(pool, n),
+            Commitments::MlDsa65(pool) => reserve_wiped(pool, n),
+            Commitments::MlDsa87(pool) => reserve_wiped(pool, n),
         }
-        if z.inf_norm() >= (gamma1 - tau * eta) {
-            continue;
+    }
+
+    // a commitment from rhoprime = H(K || rnd) with a fresh rnd for every commitment
+    pub(crate) fn push(&mut self, sk: &PreparedSk, rnd: &[u8; 32]) {
+        use crate::params::{MlDsa44, MlDsa65, MlDsa87};
+        match (sk, self) {
+            (PreparedSk::MlDsa44(sk), Commitments::MlDsa44(pool)) => push_commitment::<MlDsa44, 4, 4>(sk, pool, rnd),
+            (PreparedSk::MlDsa65(sk), Commitments::MlDsa65(pool)) => push_commitment::<MlDsa65, 6, 5>(sk, pool, rnd),
+            (PreparedSk::MlDsa87(sk), Commitments::MlDsa87(pool)) => push_commitment::<MlDsa87, 8, 7>(sk, pool, rnd),
+            _ => panic!("commitments of another security level"),
         }
+    }
+}
 
-        //Check that subtracting cs2 does not change high bits of w and low bitsdo not reveal secret information
-        let w0 = w.low_bits(gamma2);
-        let mut pv0 = PolyVec::new(w0.len); // record w - cs2
-        for i in 0..k as usize {
-            pv0.vec[i] = c.point_wise_mul(&s2.vec[i]).intt();
-            pv0.vec[i] = w0.vec[i].sub(&pv0.vec[i]);
+// the commitment is computed in its slot of the pool, which reserve made room for
+#[cfg(feature = "alloc")]
+fn push_commitment<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &ExpandedSk<K, L>,
+    pool: &mut Vec<Commitment<K, L>>,
+    rnd: &[u8; 32],
+) {
+    pool.push(Commitment::new());
+    let commitment = pool.last_mut().unwrap();
+    commit::<P, K, L>(sk, &derive_rhoprime(&sk.key, rnd, &[]), 0, commitment);
+}
+
+// sign mu with the precomputed commitments, each is used at most once whether the attempt is accepted or rejected
+// once they run out the rejection loop continues with fresh commitments from rhoprime = H(K || rnd || mu)
+#[cfg(feature = "alloc")]
+pub(crate) fn sign_mu_committed(
+    sk: &PreparedSk,
+    pool: &mut Commitments,
+    mu: &[u8; 64],
+    rnd: &[u8],
+    mode: Mode,
+) -> Vec<u8> {
+    use crate::params::{MlDsa44, MlDsa65, MlDsa87};
+    match (sk, pool) {
+        (PreparedSk::MlDsa44(sk), Commitments::MlDsa44(pool)) => {
+            sign_committed::<MlDsa44, 4, 4>(sk, pool, mu, rnd, mode)
+        }
+        (PreparedSk::MlDsa65(sk), Commitments::MlDsa65(pool)) => {
+            sign_committed::<MlDsa65, 6, 5>(sk, pool, mu, rnd, mode)
         }
-        // let r0 = pv0.low_bits(gamma2);
-        if pv0.inf_norm() >= (gamma2 - tau * eta) {
-            continue;
+        (PreparedSk::MlDsa87(sk), Commitments::MlDsa87(pool)) => {
+            sign_committed::<MlDsa87, 8, 7>(sk, pool, mu, rnd, mode)
         }
+        _ => panic!("commitments of another security level"),
+    }
+}
 
-        // Compute hints for w1
-        let mut pv1 = PolyVec::new(k as usize);
-        for i in 0..k as usize {
-            pv1.vec[i] = c.point_wise_mul(&t0.vec[i]).intt();
+#[cfg(feature = "alloc")]
+fn sign_committed<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &ExpandedSk<K, L>,
+    pool: &mut Vec<Commitment<K, L>>,
+    mu: &[u8; 64],
+    rnd: &[u8],
+    mode: Mode,
+) -> Vec<u8> {
+    let mut delta = vec![0u8; get_sizes(P::LEVEL, mode).2];
+    while let Some(commitment) = pop_wiped(pool) {
+        if attempt::<P, K, L>(sk, &commitment, mu, mode, &mut delta) {
+            return delta;
         }
+    }
+    sign_with::<P, K, L>(sk, mu, rnd, mode, &mut delta);
+    delta
+}
+
+// overwrites the commitment with y from rhoprime and nonce, w = A*y and w1
+fn commit<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &impl SecretKeyParts<K, L>,
+    rhoprime: &[u8; 64],
+    nonce: i32,
+    commitment: &mut Commitment<K, L>,
+) {
+    let Commitment { y, w, w1 } = commitment;
+    expand_y::<L>(rhoprime, nonce, P::GAMMA1, y);
+    let mut y_hat = y.copy();
+    y_hat.ntt_in_place();
+    sk.mul_A_into(&y_hat, w);
+    w.intt_in_place();
+    w.caddq();
+    for i in 0..K {
+        w1.vec[i] = w.vec[i].high_bits(P::GAMMA2);
+    }
+}
 
-        h = make_hints_pv(pv1.add(&pv0), pv1.neg(), gamma2);
-        if pv1.inf_norm() >= gamma2 {
-            continue;
+// the challenge c in the form the parameter set multiplies it with s1, s2 and t0
+// the products are at most tau * 2^12 in absolute value, the inverse NTT of c * a in (-Q, Q) is then the exact
+// product as the sparse multiplication gives it, so both give the same signatures
+// it lives on the stack for one attempt, boxing the NTT form would allocate
+#[allow(clippy::large_enum_variant)]
+enum Challenge {
+    Ntt(Poly),
+    Sparse(SparseTernary),
+}
+
+impl Challenge {
+    fn new<P: ParameterSet>(mut c: Poly) -> Challenge {
+        if P::SPARSE_CHALLENGE {
+            Challenge::Sparse(SparseTernary::new(&c))
+        } else {
+            c.ntt_in_place();
+            Challenge::Ntt(c)
         }
-        let n = count_h(&h);
-        if n > omega {
-            continue;
+    }
+
+    // out = c * a, a in the form of the expanded secret key
+    fn mul_into(&self, a: &Poly, out: &mut Poly) {
+        out.zeroize();
+        match self {
+            Challenge::Ntt(c) => {
+                out.point_wise_mul_acc(c, a);
+                out.intt_in_place();
+            }
+            Challenge::Sparse(c) => out.sparse_mul_acc(c, a),
         }
-        pass = true;
-        delta = pack_delta(&cp, &z, &h, security_level as i32, omega);
     }
 
-    delta
+    // out = c * a for a as it is unpacked, a is brought into the form of the expanded secret key first
+    fn mul_unpacked_into(&self, a: &mut Poly, out: &mut Poly) {
+        if let Challenge::Ntt(_) = self {
+            a.ntt_in_place();
+        }
+        self.mul_into(a, out);
+    }
 }
 
-pub fn verify(delta: &Vec<u8>, pk: &Vec<u8>, m: &Vec<u8>) -> bool {
-    let (k, l, eta, gamma1, gamma2, tau, omega) = get_params_sign(2);
-    let (rho, t1_ba) = unpack_pk(pk);
+// one iteration of the rejection loop, packs the signature into delta or returns false
+// if the signature would reveal the secret key or has too many hints
+// z is packed one polynomial at a time, a rejected attempt leaves a part of z in delta that is overwritten later
+fn attempt<P: ParameterSet, const K: usize, const L: usize>(
+    sk: &impl SecretKeyParts<K, L>,
+    commitment: &Commitment<K, L>,
+    mu: &[u8; 64],
+    mode: Mode,
+    delta: &mut [u8],
+) -> bool {
+    let (_, ctildebytes) = get_mode_params::<P>(mode);
+    let (y, w, w1) = (&commitment.y, &commitment.w, &commitment.w1);
+
+    let cp = challenge::<P, K>(mu, w1, ctildebytes);
+    let c = Challenge::new::<P>(sample_in_ball(&cp, P::TAU));
+    let (ctilde, z_ba) = delta.split_at_mut(ctildebytes);
+    let (z_ba, h_ba) = z_ba.split_at_mut(L * P::POLY_Z_PACKED_BYTES);
+
+    //  Compute z, reject if it reveals secret
+    let mut z = Poly::new();
+    for i in 0..L {
+        sk.mul_s1_into(&c, i, &mut z);
+        z.add_assign(&y.vec[i]);
+        z.mod_q();
+        if z.inf_norm() >= P::GAMMA1 - P::BETA {
+            return false;
+        }
+        pack_z_poly(&z, P::GAMMA1, &mut z_ba[i * P::POLY_Z_PACKED_BYTES..]);
+    }
+
+    let mut ct = Poly::new();
+    let mut h = PolyVec::<K>::new();
+    for i in 0..K {
+        //Check that subtracting cs2 does not change high bits of w and low bitsdo not reveal secret information
+        let mut r0 = w.vec[i].low_bits(P::GAMMA2); // record w - cs2
+        sk.mul_s2_into(&c, i, &mut ct);
+        r0.sub_assign(&ct);
+        r0.mod_q();
+        if r0.inf_norm() >= P::GAMMA2 - P::BETA {
+            return false;
+        }
+
+        // Compute hints for w1, the hint for r0 = w0 - cs2 + ct0 depends on the high bits w1, not on ct0
+        sk.mul_t0_into(&c, i, &mut ct);
+        if ct.inf_norm() >= P::GAMMA2 {
+            return false;
+        }
+        r0.add_assign(&ct);
+        r0.mod_q();
+        make_hints_poly(&r0, &w1.vec[i], P::GAMMA2, &mut h.vec[i]);
+    }
+    if count_h(&h) > P::OMEGA {
+        return false;
+    }
+    ctilde.copy_from_slice(&cp);
+    pack_hints(&h, P::OMEGA, h_ba);
+    true
+}
 
-    let mut A = expand_A(rho, k, l);
+// c tilde = H(mu || w1Encode(w1)), lambda/4 bytes
+fn challenge<P: ParameterSet, const K: usize>(mu: &[u8; 64], w1: &PolyVec<K>, ctildebytes: usize) -> ArrayBuf<64> {
+    // w1 has at most 8 polynomials of 192 bytes
+    let mut w1_ba = [0u8; 8 * 192];
+    let w1_ba = &mut w1_ba[..K * P::POLY_W1_PACKED_BYTES];
+    pack_w1(w1, P::GAMMA2, w1_ba);
     let mut H = Shake256::default();
-    H.update(&rho);
-    H.update(&t1_ba);
-    let mut reader = H.finalize_xof();
-    let mut tr = [0u8; 32];
-    reader.read(&mut tr);
-    H = Shake256::default();
-    H.update(&tr);
-    H.update(&m);
-    reader = H.finalize_xof();
-    let mut mu = [0u8; 64];
-    reader.read(&mut mu);
-    let (cp, z, h) = unpack_delta(delta, l, k, omega);
-    let c = sample_in_ball(cp, tau).ntt();
-    let z_hat = z.ntt();
-    let mut t1 = unpack_t1(&t1_ba, k);
+    H.update(mu);
+    H.update(w1_ba);
+    let mut cp = ArrayBuf::zeroed(ctildebytes);
+    H.finalize_xof().read(&mut cp);
+    cp
+}
+
+// verifies against the packed key, see PackedPk
+// not inlined for the stack of the lower levels, see key_pair_inner
+#[inline(never)]
+fn verify_mu_inner<P: ParameterSet, const K: usize, const L: usize>(
+    delta: &[u8],
+    pk: &[u8],
+    mu: &[u8; 64],
+    mode: Mode,
+) -> Result<(), VerifyError> {
+    verify_with::<P, K, L>(delta, &PackedPk(pk), mu, mode)
+}
+
+#[cfg(feature = "alloc")]
+fn expand_pk<const K: usize, const L: usize>(pk: &[u8]) -> ExpandedPk<K, L> {
+    let (rho, t1_ba) = unpack_pk(pk);
+    let mut t1 = PolyVec::<K>::new();
+    for i in 0..K {
+        unpack_t1_poly(&t1_ba[i * 320..], &mut t1.vec[i]);
+    }
     t1.left_shift(d as i32);
-    t1 = t1.ntt();
-    for i in 0..t1.len {
-        t1.vec[i] = c.point_wise_mul(&t1.vec[i]).neg();
-        t1.vec[i] = t1.vec[i].add(&(A[i].pointwise_acc(&z_hat))).intt();
-    }
-    t1.caddq();
-    let w1 = use_hints_pv(&h, &t1, gamma2);
-    H = Shake256::default();
-    H.update(&mu);
-    H.update(&pack_w1(&w1, gamma2, k));
-    reader = H.finalize_xof();
-    let mut cp2 = [0u8; 32];
-    reader.read(&mut cp2);
-    (z.inf_norm() < gamma1 - tau * eta) && (cp2 == cp) && (count_h(&h) <= omega)
-}
-
-#[cfg(test)]
+    t1.ntt_in_place();
+    ExpandedPk { A: expand_A::<K, L>(rho), t1_hat: t1 }
+}
+
+// A and NTT(t1 * 2^d) of a public key as verification uses them
+trait PublicKeyParts<const K: usize, const L: usize> {
+    // w = A * z for z in NTT form, reduced to (-Q, Q)
+    fn mul_A_into(&self, z: &PolyVec<L>, w: &mut PolyVec<K>);
+
+    // w[i] += c * NTT(t1[i] * 2^d) for c in NTT form
+    fn mul_t1_acc(&self, c: &Poly, w: &mut PolyVec<K>);
+}
+
+#[cfg(feature = "alloc")]
+impl<const K: usize, const L: usize> PublicKeyParts<K, L> for ExpandedPk<K, L> {
+    fn mul_A_into(&self, z: &PolyVec<L>, w: &mut PolyVec<K>) {
+        matrix_mul_into(&self.A, z, w);
+    }
+
+    fn mul_t1_acc(&self, c: &Poly, w: &mut PolyVec<K>) {
+        for i in 0..K {
+            w.vec[i].point_wise_mul_acc(c, &self.t1_hat.vec[i]);
+        }
+    }
+}
+
+// a packed public key, A is sampled from rho and t1 unpacked one polynomial at a time while verifying
+struct PackedPk<'a>(&'a [u8]);
+
+impl<const K: usize, const L: usize> PublicKeyParts<K, L> for PackedPk<'_> {
+    fn mul_A_into(&self, z: &PolyVec<L>, w: &mut PolyVec<K>) {
+        expand_A_mul_into(unpack_pk(self.0).0, z, w);
+    }
+
+    fn mul_t1_acc(&self, c: &Poly, w: &mut PolyVec<K>) {
+        let (_, t1_ba) = unpack_pk(self.0);
+        let mut t1 = Poly::new();
+        for i in 0..K {
+            unpack_t1_poly(&t1_ba[i * 320..], &mut t1);
+            t1.lshift(d as i32);
+            t1.ntt_in_place();
+            w.vec[i].point_wise_mul_acc(c, &t1);
+        }
+    }
+}
+
+fn verify_with<P: ParameterSet, const K: usize, const L: usize>(
+    delta: &[u8],
+    pk: &impl PublicKeyParts<K, L>,
+    mu: &[u8; 64],
+    mode: Mode,
+) -> Result<(), VerifyError> {
+    const { assert!(P::K == K && P::L == L) };
+    let (_, ctildebytes) = get_mode_params::<P>(mode);
+    let (_, _, sigbytes) = get_sizes(P::LEVEL, mode);
+    if delta.len() != sigbytes {
+        return Err(VerifyError::InvalidLength { pk: P::PK_BYTES, sig: delta.len() });
+    }
+
+    // the last k bytes are the hint counts, a count above omega is reported as too many hints
+    // rather than as a malformed encoding
+    if delta[delta.len() - K..].iter().any(|&n| n as i32 > P::OMEGA) {
+        return Err(VerifyError::TooManyHints);
+    }
+    let mut z = PolyVec::<L>::new();
+    let mut h = PolyVec::<K>::new();
+    let cp = unpack_delta::<K, L>(delta, P::GAMMA1, P::OMEGA, ctildebytes, &mut z, &mut h)
+        .map_err(|_| VerifyError::MalformedEncoding)?;
+    if z.inf_norm() >= P::GAMMA1 - P::BETA {
+        return Err(VerifyError::ZNormTooLarge);
+    }
+    if count_h(&h) > P::OMEGA {
+        return Err(VerifyError::TooManyHints);
+    }
+
+    // w = A * z - c * t1 * 2^d, the accumulation of -c * t1 onto A * z in (-Q, Q) stays in (-2Q, 2Q)
+    let mut c = sample_in_ball(cp, P::TAU);
+    c.ntt_in_place();
+    c.neg_assign();
+    z.ntt_in_place();
+    let mut w = PolyVec::<K>::new();
+    pk.mul_A_into(&z, &mut w);
+    pk.mul_t1_acc(&c, &mut w);
+    w.mod_q();
+    w.intt_in_place();
+    w.caddq();
+    use_hints_pv(&h, &mut w, P::GAMMA2);
+    let cp2 = challenge::<P, K>(mu, &w, ctildebytes);
+    if !ct_eq(&cp2, cp) {
+        return Err(VerifyError::ChallengeMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "alloc")]
+fn check_sk_inner<P: ParameterSet, const K: usize, const L: usize>(sk: &[u8], mode: Mode) -> bool {
+    let (trbytes, _) = get_mode_params::<P>(mode);
+    let (_, _, _, s1, s2, _) = unpack_sk::<K, L>(sk, P::ETA, trbytes);
+    s1.vec
+        .iter()
+        .chain(s2.vec.iter())
+        .all(|p| p.coeffs.iter().all(|c| c.abs() <= P::ETA))
+}
+
+#[cfg(feature = "alloc")]
+fn check_signature_inner<P: ParameterSet, const K: usize, const L: usize>(
+    delta: &[u8],
+    mode: Mode,
+) -> Result<(), Error> {
+    let (_, ctildebytes) = get_mode_params::<P>(mode);
+    let mut z = PolyVec::<L>::new();
+    let mut h = PolyVec::<K>::new();
+    unpack_delta::<K, L>(delta, P::GAMMA1, P::OMEGA, ctildebytes, &mut z, &mut h).map(|_| ())
+}
+
+#[cfg(all(test, feature = "std"))]
 mod test {
+    use crate::params::{MlDsa44, MlDsa65, MlDsa87, Mode, ParameterSet};
     use sha3::{
         digest::{ExtendableOutput, Update, XofReader},
         Shake256,
     };
 
+    const SEED: [u8; 32] = [
+        0x9f, 0xd9, 0xaa, 0xfd, 0x8f, 0xc9, 0x01, 0xf5, 0x00, 0x85, 0xde, 0x82, 0x68, 0xc2, 0xd6,
+        0x30, 0x26, 0xdd, 0x8e, 0x35, 0xf8, 0x9d, 0xd1, 0xe2, 0xbc, 0x15, 0x1d, 0x7d, 0x20, 0xd0,
+        0x97, 0x96,
+    ];
+
+    const MSG: [u8; 32] = [
+        0xea, 0xcd, 0xc0, 0x82, 0x36, 0x1d, 0xe7, 0x10, 0x1b, 0x69, 0x6e, 0xe1, 0xa0, 0xa4, 0xf3,
+        0x51, 0x4a, 0x65, 0xb6, 0xcf, 0xb3, 0x42, 0x0b, 0xa4, 0x6a, 0x8d, 0x41, 0x10, 0x2f, 0xdf,
+        0xa2, 0x47,
+    ];
+
+    fn shake256_digest(bytes: &[u8]) -> [u8; 32] {
+        let mut H = Shake256::default();
+        H.update(bytes);
+        let mut reader = H.finalize_xof();
+        let mut out = [0u8; 32];
+        reader.read(&mut out);
+        out
+    }
+
     #[test]
     fn test_shake256() {
         let seed = [
@@ -535,9 +1381,9 @@ mod test {
         ];
 
         let security_level = 2;
-        let (pk, sk) = super::key_pair(&seed, security_level);
+        let (pk, sk) = super::key_pair(&seed, security_level).unwrap();
         assert_eq!(pk, pk_ref);
-        assert_eq!(sk, sk_ref);
+        assert_eq!(sk[..], sk_ref);
     }
 
     #[test]
@@ -547,13 +1393,13 @@ mod test {
             0xd6, 0x30, 0x26, 0xdd, 0x8e, 0x35, 0xf8, 0x9d, 0xd1, 0xe2, 0xbc, 0x15, 0x1d, 0x7d,
             0x20, 0xd0, 0x97, 0x96,
         ];
-        let (_pk, sk) = super::key_pair(&seed, 2);
+        let (_pk, sk) = super::key_pair(&seed, 2).unwrap();
         let msg = [
             0xea, 0xcd, 0xc0, 0x82, 0x36, 0x1d, 0xe7, 0x10, 0x1b, 0x69, 0x6e, 0xe1, 0xa0, 0xa4,
             0xf3, 0x51, 0x4a, 0x65, 0xb6, 0xcf, 0xb3, 0x42, 0xb, 0xa4, 0x6a, 0x8d, 0x41, 0x10,
             0x2f, 0xdf, 0xa2, 0x47,
         ];
-        let sig = super::sign(&sk, &msg.to_vec(), 2);
+        let sig = super::sign(&sk, &msg, 2).unwrap();
 
         let sig_ref: [u8; 2420] = [
             0xd0, 0xfb, 0xa5, 0x8a, 0xf9, 0xf5, 0x2f, 0x29, 0xcf, 0xc7, 0x24, 0x11, 0xcd, 0xe9,
@@ -739,13 +1585,253 @@ mod test {
             0xd6, 0x30, 0x26, 0xdd, 0x8e, 0x35, 0xf8, 0x9d, 0xd1, 0xe2, 0xbc, 0x15, 0x1d, 0x7d,
             0x20, 0xd0, 0x97, 0x96,
         ];
-        let (pk, sk) = key_pair(&seed, 2);
+        let (pk, sk) = key_pair(&seed, 2).unwrap();
         let msg = [
             0xea, 0xcd, 0xc0, 0x82, 0x36, 0x1d, 0xe7, 0x10, 0x1b, 0x69, 0x6e, 0xe1, 0xa0, 0xa4,
             0xf3, 0x51, 0x4a, 0x65, 0xb6, 0xcf, 0xb3, 0x42, 0xb, 0xa4, 0x6a, 0x8d, 0x41, 0x10,
             0x2f, 0xdf, 0xa2, 0x47,
         ];
-        let sig = sign(&sk, &msg.to_vec(), 2);
-        assert!(verify(&sig, &pk, &msg.to_vec()));
+        let sig = sign(&sk, &msg, 2).unwrap();
+        assert!(verify(&sig, &pk, &msg));
+    }
+
+    #[test]
+    fn test_key_gen_sign_level3() {
+        // SHAKE256 digests of the pk, sk and signature produced by the reference implementation
+        let pk_digest = [
+            0xa1, 0x46, 0x9e, 0x2f, 0xe2, 0xf9, 0xb9, 0x6d, 0xeb, 0xf7, 0xc0, 0x83, 0x23, 0x37,
+            0x61, 0xaa, 0x6d, 0x84, 0x1d, 0xfa, 0x99, 0x3e, 0x23, 0x83, 0xa1, 0xac, 0xb0, 0xa4,
+            0x1b, 0x7d, 0xc5, 0xa4,
+        ];
+        let sk_digest = [
+            0x2a, 0x04, 0xfa, 0x1b, 0xb0, 0x68, 0x6d, 0x77, 0xff, 0xb0, 0x2f, 0x36, 0x66, 0x04,
+            0x1f, 0xd8, 0xbf, 0x57, 0x42, 0xc4, 0xf6, 0x1d, 0x21, 0xa3, 0x27, 0x20, 0x3a, 0x61,
+            0xc8, 0x12, 0x09, 0xfa,
+        ];
+        let sig_digest = [
+            0xfc, 0x44, 0x24, 0x42, 0xd4, 0x77, 0xc5, 0x3d, 0xca, 0xcc, 0xdb, 0x33, 0xe0, 0x87,
+            0x57, 0xe6, 0xfd, 0x74, 0xdb, 0x49, 0x3b, 0xf7, 0x62, 0x91, 0x14, 0x74, 0x6d, 0xcf,
+            0xb1, 0x5c, 0x3c, 0xc5,
+        ];
+
+        let (pk, sk) = super::key_pair(&SEED, 3).unwrap();
+        let sig = super::sign(&sk, &MSG, 3).unwrap();
+        assert_eq!(shake256_digest(&pk), pk_digest);
+        assert_eq!(shake256_digest(&sk), sk_digest);
+        assert_eq!(shake256_digest(&sig), sig_digest);
+    }
+
+    #[test]
+    fn test_key_gen_sign_level5() {
+        // SHAKE256 digests of the pk, sk and signature produced by the reference implementation
+        let pk_digest = [
+            0x1b, 0xad, 0x27, 0xad, 0x4e, 0x47, 0xc9, 0x02, 0x92, 0x2c, 0x5b, 0x63, 0xf8, 0x14,
+            0x7c, 0x40, 0x71, 0x46, 0x66, 0x92, 0x43, 0xdf, 0x80, 0x26, 0xc9, 0x22, 0x02, 0xee,
+            0xf4, 0x79, 0xa1, 0xfb,
+        ];
+        let sk_digest = [
+            0x82, 0xdd, 0x5d, 0x3c, 0x41, 0xf7, 0x57, 0x0c, 0x58, 0xa3, 0x88, 0x11, 0x32, 0xc2,
+            0x25, 0xaa, 0x90, 0x2a, 0x56, 0x10, 0xb4, 0x62, 0x08, 0xc1, 0xc1, 0x68, 0x17, 0x57,
+            0xd0, 0x69, 0x11, 0xc6,
+        ];
+        let sig_digest = [
+            0x97, 0x86, 0x46, 0xad, 0xf1, 0xf3, 0x67, 0xb3, 0xe9, 0x56, 0x6a, 0x1b, 0x3c, 0xa6,
+            0xa3, 0xf4, 0xb3, 0x98, 0x9a, 0x1b, 0x32, 0x64, 0x74, 0x06, 0x8f, 0x04, 0xe2, 0x05,
+            0x70, 0xe9, 0xa0, 0x40,
+        ];
+
+        let (pk, sk) = super::key_pair(&SEED, 5).unwrap();
+        let sig = super::sign(&sk, &MSG, 5).unwrap();
+        assert_eq!(shake256_digest(&pk), pk_digest);
+        assert_eq!(shake256_digest(&sk), sk_digest);
+        assert_eq!(shake256_digest(&sig), sig_digest);
+    }
+
+    // a parameter set that multiplies the challenge the other way
+    struct OtherChallengeMul<P>(core::marker::PhantomData<P>);
+
+    impl<P: ParameterSet> ParameterSet for OtherChallengeMul<P> {
+        const LEVEL: u8 = P::LEVEL;
+        const K: usize = P::K;
+        const L: usize = P::L;
+        const ETA: i32 = P::ETA;
+        const GAMMA1: i32 = P::GAMMA1;
+        const GAMMA2: i32 = P::GAMMA2;
+        const TAU: i32 = P::TAU;
+        const OMEGA: i32 = P::OMEGA;
+        const CTILDE_BYTES: usize = P::CTILDE_BYTES;
+        const SPARSE_CHALLENGE: bool = !P::SPARSE_CHALLENGE;
+    }
+
+    fn check_challenge_mul<P: ParameterSet, const K: usize, const L: usize>() {
+        let (_, sk) = super::key_pair(&SEED, P::LEVEL).unwrap();
+        let sig_len = super::get_sizes(P::LEVEL, Mode::Dilithium).2;
+        for i in 0..20u8 {
+            let (m, rnd) = (&MSG[..i as usize], [i; 32]);
+            let mut sig = vec![0u8; sig_len];
+            let mut other = vec![0u8; sig_len];
+            super::sign_inner::<P, K, L>(&sk, &[], m, &rnd, Mode::Dilithium, &mut sig);
+            super::sign_inner::<OtherChallengeMul<P>, K, L>(&sk, &[], m, &rnd, Mode::Dilithium, &mut other);
+            assert_eq!(sig, other, "level {}", P::LEVEL);
+        }
+    }
+
+    #[test]
+    fn test_sparse_challenge_same_signatures() {
+        check_challenge_mul::<MlDsa44, 4, 4>();
+        check_challenge_mul::<MlDsa65, 6, 5>();
+        check_challenge_mul::<MlDsa87, 8, 7>();
+    }
+
+    #[test]
+    fn test_sign_hint_boundary() {
+        // w0 - cs2 + ct0 hits -gamma2 for one coefficient, so the hint depends on w1
+        let seed = [
+            0x27, 0x9a, 0xd4, 0xa5, 0x90, 0x59, 0x9e, 0x81, 0x6f, 0x8e, 0x55, 0xb7, 0xe9, 0x71,
+            0x37, 0x0c, 0xe7, 0x39, 0x28, 0x89, 0x68, 0x05, 0x0a, 0x22, 0x63, 0x97, 0x42, 0x76,
+            0x17, 0xa0, 0x0a, 0x57,
+        ];
+        let msg = [
+            0x26, 0xf9, 0x07, 0x69, 0x3f, 0xc3, 0x57, 0x44, 0x93, 0xb3, 0x7f, 0x8b, 0xf4, 0xf4,
+            0x75, 0x0e, 0x6c, 0x2a, 0xca, 0xcf, 0x30, 0x05, 0x6f, 0x9d, 0x22, 0x23, 0x1e, 0x94,
+            0xe0, 0xac, 0xfb, 0x76,
+        ];
+        let sig_digest = [
+            0x38, 0x34, 0xd8, 0xc8, 0x53, 0xb1, 0x28, 0x45, 0x42, 0x4e, 0x15, 0xdf, 0x3b, 0xc1,
+            0x3d, 0xe7, 0xaa, 0x16, 0xb5, 0xdc, 0xaa, 0x73, 0x18, 0x3d, 0xe7, 0x4d, 0x7c, 0xd9,
+            0x0e, 0x01, 0x51, 0x58,
+        ];
+
+        let (pk, sk) = super::key_pair(&seed, 2).unwrap();
+        let sig = super::sign(&sk, &msg, 2).unwrap();
+        assert_eq!(shake256_digest(&sig), sig_digest);
+        assert!(super::verify(&sig, &pk, &msg));
+    }
+
+    #[test]
+    fn test_public_key() {
+        use super::*;
+        use crate::error::Error;
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            assert_eq!(public_key(&sk), Ok(pk));
+
+            // rho, K, tr, s1, s2 and t0 follow each other, tr has 32 bytes
+            let mut bad_sk = sk.clone();
+            bad_sk[70] ^= 1;
+            assert_eq!(public_key(&bad_sk), Err(Error::TrMismatch));
+
+            let mut bad_sk = sk.clone();
+            bad_sk[0] ^= 1;
+            assert_eq!(public_key(&bad_sk), Err(Error::TrMismatch));
+
+            // the first coefficient of s1 becomes eta - 7 or eta - 15
+            let mut bad_sk = sk.clone();
+            bad_sk[96] |= 0x0f;
+            assert_eq!(public_key(&bad_sk), Err(Error::SecretOutOfRange));
+
+            // the last coefficient of t0
+            let n = sk.len();
+            let mut bad_sk = sk.clone();
+            bad_sk[n - 1] ^= 1;
+            assert_eq!(public_key(&bad_sk), Err(Error::T0Mismatch));
+        }
+        assert_eq!(public_key(&[0u8; 10]), Err(Error::InvalidLength(10)));
+    }
+
+    #[test]
+    fn test_verify_all_levels() {
+        use super::*;
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            let sig = sign(&sk, &MSG, security_level).unwrap();
+            assert!(verify(&sig, &pk, &MSG));
+
+            let mut bad_msg = MSG;
+            bad_msg[0] ^= 1;
+            assert!(!verify(&sig, &pk, &bad_msg));
+
+            let mut bad_sig = sig.clone();
+            bad_sig[0] ^= 1;
+            assert!(!verify(&bad_sig, &pk, &MSG));
+
+            // a truncated signature does not match any parameter set
+            assert!(!verify(&sig[..sig.len() - 1], &pk, &MSG));
+        }
+    }
+
+    #[test]
+    fn test_verify_detailed() {
+        use super::*;
+        use crate::error::VerifyError;
+        use crate::params::MlDsa44;
+        let (pk, sk) = key_pair(&SEED, 2).unwrap();
+        let sig = sign(&sk, &MSG, 2).unwrap();
+        let n = sig.len();
+        assert_eq!(verify_detailed(&sig, &pk, &MSG), Ok(()));
+
+        let mut bad_msg = MSG;
+        bad_msg[0] ^= 1;
+        assert_eq!(verify_detailed(&sig, &pk, &bad_msg), Err(VerifyError::ChallengeMismatch));
+
+        assert_eq!(
+            verify_detailed(&sig[..n - 1], &pk, &MSG),
+            Err(VerifyError::InvalidLength { pk: pk.len(), sig: n - 1 })
+        );
+
+        // the hint counts are stored in the last k bytes
+        let mut bad_sig = sig.clone();
+        bad_sig[n - 1] = MlDsa44::OMEGA as u8 + 1;
+        assert_eq!(verify_detailed(&bad_sig, &pk, &MSG), Err(VerifyError::TooManyHints));
+
+        // a non-zero byte after the last hint index
+        let mut bad_sig = sig.clone();
+        let hints = n - MlDsa44::OMEGA as usize - 4;
+        bad_sig[hints + sig[n - 1] as usize] = 1;
+        assert_eq!(verify_detailed(&bad_sig, &pk, &MSG), Err(VerifyError::MalformedEncoding));
+
+        // a coefficient of z on the bound gamma1 - beta
+        use crate::pack::pack_delta;
+        let (mut z, mut h) = (PolyVec::<4>::new(), PolyVec::<4>::new());
+        let cp = unpack_delta::<4, 4>(&sig, MlDsa44::GAMMA1, MlDsa44::OMEGA, 32, &mut z, &mut h).unwrap();
+        let mut bad_sig = vec![0u8; n];
+        z.vec[0].coeffs[0] = MlDsa44::GAMMA1 - MlDsa44::BETA;
+        pack_delta(cp, &z, &h, MlDsa44::GAMMA1, MlDsa44::OMEGA, &mut bad_sig);
+        assert_eq!(verify_detailed(&bad_sig, &pk, &MSG), Err(VerifyError::ZNormTooLarge));
+        assert!(!verify(&bad_sig, &pk, &MSG));
+        z.vec[0].coeffs[0] = -(MlDsa44::GAMMA1 - MlDsa44::BETA);
+        pack_delta(cp, &z, &h, MlDsa44::GAMMA1, MlDsa44::OMEGA, &mut bad_sig);
+        assert_eq!(verify_detailed(&bad_sig, &pk, &MSG), Err(VerifyError::ZNormTooLarge));
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        use super::*;
+        let mut sigs = Vec::new();
+        let mut pks = Vec::new();
+        for security_level in [2, 3, 5] {
+            let (pk, sk) = key_pair(&SEED, security_level).unwrap();
+            sigs.push(sign(&sk, &MSG, security_level).unwrap());
+            pks.push(pk);
+        }
+        let mut bad_msg = MSG;
+        bad_msg[0] ^= 1;
+        let items: Vec<(&[u8], &[u8], &[u8])> = vec![
+            (&sigs[0], &pks[0], &MSG),
+            (&sigs[1], &pks[1], &MSG),
+            (&sigs[2], &pks[2], &MSG),
+            (&sigs[0], &pks[0], &bad_msg),
+            (&sigs[1], &pks[0], &MSG),
+        ];
+        assert_eq!(
+            verify_batch(&items),
+            vec![
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Err(VerifyError::ChallengeMismatch),
+                Err(VerifyError::InvalidLength { pk: 1312, sig: 3293 }),
+            ]
+        );
     }
 }
@@ -0,0 +1,266 @@
+// dudect-style leakage tests: each function runs on a fixed input and on random inputs
+// in random order, and Welch's t-test compares the cycle counts of the two classes
+// they take long and depend on the load of the machine, so they only run with --ignored
+use crate::mldsa::{key_pair, sign_with_rnd};
+use crate::pack::{pack_delta, pack_sk, unpack_delta, unpack_sk, unpack_y};
+use crate::params::Q;
+use crate::poly::Poly;
+use crate::polyvec::polyvec::PolyVec;
+use crate::reduce::montgomery_reduce;
+use crate::rounding::make_hints_pv;
+use core::arch::x86_64::_rdtsc;
+use rand::rngs::ThreadRng;
+use rand::{Rng, RngCore};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake256;
+use std::hint::black_box;
+
+// |t| above 4.5 is a likely leak for dudect and fails a test, |t| above 10 is a certain one
+const T_THRESHOLD: f64 = 4.5;
+const T_CERTAIN: f64 = 10.0;
+// measurements above this percentile are interrupts and cache misses of the machine
+const CROP_PERCENTILE: f64 = 0.9;
+const GAMMA2: i32 = (Q - 1) / 88;
+const GAMMA1: i32 = 1 << 17;
+
+fn cpucycles() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+// the fewest cycles between two reads of the counter
+fn cpucycles_overhead() -> u64 {
+    (0..1000)
+        .map(|_| {
+            let t0 = cpucycles();
+            let t1 = black_box(cpucycles());
+            t1 - t0
+        })
+        .min()
+        .unwrap()
+}
+
+// Welford's running mean and variance of each class
+#[derive(Default)]
+struct TTest {
+    n: [f64; 2],
+    mean: [f64; 2],
+    m2: [f64; 2],
+}
+
+impl TTest {
+    fn push(&mut self, class: usize, x: f64) {
+        self.n[class] += 1.0;
+        let delta = x - self.mean[class];
+        self.mean[class] += delta / self.n[class];
+        self.m2[class] += delta * (x - self.mean[class]);
+    }
+
+    fn t(&self) -> f64 {
+        let var0 = self.m2[0] / (self.n[0] - 1.0);
+        let var1 = self.m2[1] / (self.n[1] - 1.0);
+        (self.mean[0] - self.mean[1]) / (var0 / self.n[0] + var1 / self.n[1]).sqrt()
+    }
+}
+
+// class 0 runs on inputs from fixed, class 1 on inputs from random, all prepared before measuring
+fn dudect<T>(
+    name: &str,
+    n: usize,
+    mut fixed: impl FnMut(&mut ThreadRng) -> T,
+    mut random: impl FnMut(&mut ThreadRng) -> T,
+    mut f: impl FnMut(&T),
+) -> f64 {
+    let mut rng = rand::thread_rng();
+    let classes: Vec<usize> = (0..n).map(|_| rng.gen_range(0..2)).collect();
+    let inputs: Vec<T> = classes
+        .iter()
+        .map(|&class| if class == 0 { fixed(&mut rng) } else { random(&mut rng) })
+        .collect();
+    let overhead = cpucycles_overhead();
+    let mut cycles = Vec::with_capacity(n);
+    for input in &inputs {
+        let t0 = cpucycles();
+        f(black_box(input));
+        let t1 = cpucycles();
+        cycles.push((t1 - t0).saturating_sub(overhead));
+    }
+
+    let mut sorted = cycles.clone();
+    sorted.sort_unstable();
+    let crop = sorted[(n as f64 * CROP_PERCENTILE) as usize];
+    let mut ttest = TTest::default();
+    for (&class, &c) in classes.iter().zip(&cycles) {
+        if c <= crop {
+            ttest.push(class, c as f64);
+        }
+    }
+    let t = ttest.t();
+    let verdict = match t.abs() {
+        a if a > T_CERTAIN => "certain leak",
+        a if a > T_THRESHOLD => "likely leak",
+        _ => "no leak found",
+    };
+    println!(
+        "{}: t = {:.2} ({}), mean cycles: fixed {:.0}, random {:.0}",
+        name, t, verdict, ttest.mean[0], ttest.mean[1]
+    );
+    t
+}
+
+fn random_poly(rng: &mut ThreadRng, bound: i32) -> Poly {
+    let mut a = Poly::new();
+    for c in a.coeffs.iter_mut() {
+        *c = rng.gen_range(-bound..=bound);
+    }
+    a
+}
+
+fn random_polyvec<const N: usize>(rng: &mut ThreadRng, bound: i32) -> PolyVec<N> {
+    let mut v = PolyVec::new();
+    for p in v.vec.iter_mut() {
+        *p = random_poly(rng, bound);
+    }
+    v
+}
+
+// the harness must see the early exit of a plain slice comparison, or it proves nothing
+#[test]
+#[ignore]
+fn test_timing_detects_leak() {
+    let secret = vec![0x3cu8; 4096];
+    let t = dudect("slice ==", 20000, |_| secret.clone(), |rng| {
+        let mut guess = vec![0u8; 4096];
+        rng.fill_bytes(&mut guess);
+        guess
+    }, |guess| {
+        black_box(guess[..] == secret[..]);
+    });
+    assert!(t.abs() > T_CERTAIN, "the early exit of == went unnoticed, t = {:.2}", t);
+}
+
+// whether the first attempt of sign is accepted, its z - y is c * s1, whose coefficients are at most tau * eta = 78,
+// while z - y of a later attempt is far larger
+fn first_attempt(sk: &[u8], rnd: &[u8; 32], sig: &[u8]) -> bool {
+    // mu = H(tr || 0 || 0 || m) for an empty ctx, rhoprime = H(K || rnd || mu)
+    let mut mu = [0u8; 64];
+    let mut h = Shake256::default();
+    h.update(&sk[64..128]);
+    h.update(&[0, 0]);
+    h.update(b"message");
+    h.finalize_xof().read(&mut mu);
+    let mut rhoprime = [0u8; 64];
+    let mut h = Shake256::default();
+    h.update(&sk[32..64]);
+    h.update(rnd);
+    h.update(&mu);
+    h.finalize_xof().read(&mut rhoprime);
+    let mut buf = [0u8; 576];
+    let mut h = Shake256::default();
+    h.update(&rhoprime);
+    h.update(&[0, 0]);
+    h.finalize_xof().read(&mut buf);
+    let y = unpack_y(GAMMA1, &buf);
+    let (mut z, mut h) = (PolyVec::<4>::new(), PolyVec::<4>::new());
+    unpack_delta::<4, 4>(sig, GAMMA1, 80, 32, &mut z, &mut h).unwrap();
+    z.vec[0].coeffs.iter().zip(&y.coeffs).all(|(z, y)| (z - y).abs() <= 78)
+}
+
+#[test]
+#[ignore]
+fn test_timing_sign() {
+    // the number of attempts is public but its distribution depends on the key, so both classes
+    // only sign with a rnd whose first attempt is accepted and differ in the key alone
+    let (_pk, fixed) = key_pair(&[0u8; 32], 2).unwrap();
+    let input = |sk: Vec<u8>, rng: &mut ThreadRng| loop {
+        let mut rnd = [0u8; 32];
+        rng.fill_bytes(&mut rnd);
+        if first_attempt(&sk, &rnd, &sign_with_rnd(&sk, b"message", b"", &rnd, 2).unwrap()) {
+            return (sk, rnd);
+        }
+    };
+    let t = dudect("sign", 10000, |rng| input(fixed.to_vec(), rng), |rng| {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        input(key_pair(&seed, 2).unwrap().1.to_vec(), rng)
+    }, |(sk, rnd)| {
+        black_box(sign_with_rnd(sk, b"message", b"", rnd, 2).unwrap());
+    });
+    assert!(t.abs() < T_THRESHOLD, "sign leaks, t = {:.2}", t);
+}
+
+#[test]
+#[ignore]
+fn test_timing_ntt() {
+    let t = dudect("Poly::ntt", 20000, |_| Poly::new(), |rng| random_poly(rng, Q - 1), |a| {
+        black_box(a.ntt());
+    });
+    assert!(t.abs() < T_THRESHOLD, "Poly::ntt leaks, t = {:.2}", t);
+}
+
+#[test]
+#[ignore]
+fn test_timing_montgomery_reduce() {
+    let t = dudect("montgomery_reduce", 20000, |_| [0i64; 256], |rng| {
+        let mut a = [0i64; 256];
+        for x in a.iter_mut() {
+            *x = rng.gen_range(-(Q as i64) * (Q as i64)..(Q as i64) * (Q as i64));
+        }
+        a
+    }, |a| {
+        for &x in a.iter() {
+            black_box(montgomery_reduce(x));
+        }
+    });
+    assert!(t.abs() < T_THRESHOLD, "montgomery_reduce leaks, t = {:.2}", t);
+}
+
+#[test]
+#[ignore]
+fn test_timing_make_hints() {
+    // r and z cover both sides of gamma2, as w0 - cs2 + ct0 and ct0 do in sign
+    let t = dudect("make_hints_pv", 20000, |_| (PolyVec::<4>::new(), PolyVec::<4>::new()), |rng| {
+        (random_polyvec(rng, 2 * GAMMA2), random_polyvec(rng, GAMMA2))
+    }, |(r, z)| {
+        black_box(make_hints_pv(r, z, GAMMA2));
+    });
+    assert!(t.abs() < T_THRESHOLD, "make_hints_pv leaks, t = {:.2}", t);
+}
+
+#[test]
+#[ignore]
+fn test_timing_pack_sk() {
+    // s1 and s2 in [-eta, eta] and t0 in (-2^12, 2^12] of level 2
+    let secrets = |rng: &mut ThreadRng| {
+        (random_polyvec::<4>(rng, 2), random_polyvec::<4>(rng, 2), random_polyvec::<4>(rng, 1 << 12))
+    };
+    let t = dudect("pack_sk", 20000, |_| (PolyVec::new(), PolyVec::new(), PolyVec::new()), secrets, |(s1, s2, t0)| {
+        let mut sk = [0u8; 2560];
+        pack_sk::<4, 4>(&[0u8; 32], &[0u8; 32], &[0u8; 64], s1, s2, t0, 2, &mut sk);
+        black_box(sk);
+    });
+    assert!(t.abs() < T_THRESHOLD, "pack_sk leaks, t = {:.2}", t);
+
+    let packed = |(s1, s2, t0): (PolyVec<4>, PolyVec<4>, PolyVec<4>)| {
+        let mut sk = vec![0u8; 2560];
+        pack_sk::<4, 4>(&[0u8; 32], &[0u8; 32], &[0u8; 64], &s1, &s2, &t0, 2, &mut sk);
+        sk
+    };
+    let fixed = packed((PolyVec::new(), PolyVec::new(), PolyVec::new()));
+    let t = dudect("unpack_sk", 20000, |_| fixed.clone(), |rng| packed(secrets(rng)), |sk| {
+        black_box(unpack_sk::<4, 4>(sk, 2, 64));
+    });
+    assert!(t.abs() < T_THRESHOLD, "unpack_sk leaks, t = {:.2}", t);
+}
+
+#[test]
+#[ignore]
+fn test_timing_pack_z() {
+    // the hints are public and stay empty, only z changes
+    let h = PolyVec::<4>::new();
+    let t = dudect("pack_delta", 20000, |_| PolyVec::<4>::new(), |rng| random_polyvec(rng, GAMMA1 - 1), |z| {
+        let mut sig = [0u8; 2420];
+        pack_delta::<4, 4>(&[0u8; 32], z, &h, GAMMA1, 80, &mut sig);
+        black_box(sig);
+    });
+    assert!(t.abs() < T_THRESHOLD, "pack_delta leaks, t = {:.2}", t);
+}
@@ -0,0 +1,147 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "alloc")]
+use zeroize::Zeroize;
+
+// a byte string of at most N bytes on the stack, for tr, c tilde and the framing of the message without alloc
+#[derive(Clone, Copy)]
+pub struct ArrayBuf<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayBuf<N> {
+    // len zero bytes
+    pub fn zeroed(len: usize) -> ArrayBuf<N> {
+        assert!(len <= N);
+        ArrayBuf { bytes: [0u8; N], len }
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+}
+
+impl<const N: usize> Deref for ArrayBuf<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> DerefMut for ArrayBuf<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..self.len]
+    }
+}
+
+// f applied to every input on as many threads as there are cores, the results are in the order of the inputs
+// the threads take the next unprocessed input, so a few slow inputs do not hold up a whole chunk
+#[cfg(feature = "std")]
+pub fn parallel_map<T: Sync, R: Send>(inputs: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(inputs.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<R>> = inputs.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= inputs.len() {
+                            break done;
+                        }
+                        done.push((i, f(&inputs[i])));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (i, r) in worker.join().unwrap() {
+                results[i] = Some(r);
+            }
+        }
+    });
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+// a == b without an early exit, the time depends only on the lengths
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    core::hint::black_box(diff) == 0
+}
+
+// reserve room for additional elements without freeing a buffer that still holds copies of the elements
+#[cfg(feature = "alloc")]
+pub fn reserve_wiped<T: Zeroize>(v: &mut Vec<T>, additional: usize) {
+    if v.capacity() - v.len() >= additional {
+        return;
+    }
+    let mut grown = Vec::with_capacity(v.len() + additional);
+    grown.append(v);
+    // v is empty, this wipes its whole buffer
+    v.zeroize();
+    *v = grown;
+}
+
+// remove the last element and wipe the slot it leaves behind in the buffer
+#[cfg(feature = "alloc")]
+pub fn pop_wiped<T>(v: &mut Vec<T>) -> Option<T> {
+    let last = v.pop()?;
+    v.spare_capacity_mut()[0].zeroize();
+    Some(last)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_array_buf() {
+        let mut buf = ArrayBuf::<8>::zeroed(2);
+        buf.extend_from_slice(b"abc");
+        assert_eq!(&buf[..], b"\0\0abc");
+        buf[0] = 1;
+        assert_eq!(buf.len(), 5);
+        assert_eq!(buf[0], 1);
+    }
+
+    #[test]
+    fn test_parallel_map() {
+        let inputs: Vec<u64> = (0..1000).collect();
+        assert_eq!(parallel_map(&inputs, |x| x * x), inputs.iter().map(|x| x * x).collect::<Vec<_>>());
+        assert!(parallel_map(&[] as &[u64], |x| *x).is_empty());
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq(b"challenge", b"challenge"));
+        assert!(!ct_eq(b"challenge", b"challengf"));
+        assert!(!ct_eq(b"challenge", b"Challenge"));
+        assert!(!ct_eq(b"challenge", b"challenge!"));
+        assert!(ct_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_reserve_and_pop_wiped() {
+        let mut v: Vec<u64> = vec![7, 8];
+        reserve_wiped(&mut v, 10);
+        assert_eq!(v, [7, 8]);
+        assert!(v.capacity() >= 12);
+        assert_eq!(pop_wiped(&mut v), Some(8));
+        assert_eq!(unsafe { v.spare_capacity_mut()[0].assume_init() }, 0);
+        assert_eq!(pop_wiped(&mut v), Some(7));
+        assert_eq!(pop_wiped(&mut v), None);
+    }
+}
@@ -0,0 +1,71 @@
+// key generation, signing and verification into caller buffers make no heap allocation,
+// the allocations of the thread running the test are counted, so this file holds a single test
+use dilithium_rust::mldsa;
+use dilithium_rust::params::{MlDsa44, MlDsa65, MlDsa87, ParameterSet};
+use dilithium_rust::sign;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let r = f();
+    (r, ALLOCATIONS.with(Cell::get) - before)
+}
+
+fn check<P: ParameterSet, const PK: usize, const SK: usize, const SIG: usize>() {
+    let (mut pk, mut sk, mut sig) = ([0u8; PK], [0u8; SK], [0u8; SIG]);
+    let msg = [0x42u8; 100];
+    let (r, n) = allocations(|| mldsa::key_pair_into(&[P::LEVEL; 32], P::LEVEL, &mut pk, &mut sk));
+    r.unwrap();
+    assert_eq!(n, 0, "key_pair_into at level {}", P::LEVEL);
+    // several messages, so that some signatures take more than one iteration of the rejection loop
+    for i in 0..20u8 {
+        let (r, n) = allocations(|| mldsa::sign_into(&sk, &msg[..i as usize], b"ctx", &[i; 32], P::LEVEL, &mut sig));
+        r.unwrap();
+        assert_eq!(n, 0, "sign_into at level {}", P::LEVEL);
+        let (ok, n) = allocations(|| mldsa::verify(&sig, &pk, &msg[..i as usize], b"ctx"));
+        assert!(ok);
+        assert_eq!(n, 0, "verify at level {}", P::LEVEL);
+    }
+}
+
+#[test]
+pub fn test_no_heap_allocation() {
+    check::<MlDsa44, { MlDsa44::PK_BYTES }, { MlDsa44::SK_BYTES }, { MlDsa44::SIG_BYTES }>();
+    check::<MlDsa65, { MlDsa65::PK_BYTES }, { MlDsa65::SK_BYTES }, { MlDsa65::SIG_BYTES }>();
+    check::<MlDsa87, { MlDsa87::PK_BYTES }, { MlDsa87::SK_BYTES }, { MlDsa87::SIG_BYTES }>();
+
+    // round 3 keys have a 32 bytes tr
+    let mut pk = [0u8; MlDsa44::PK_BYTES];
+    let mut sk = [0u8; MlDsa44::SK_BYTES - 32];
+    let mut sig = [0u8; MlDsa44::SIG_BYTES];
+    let (r, n) = allocations(|| sign::key_pair_into(&[4u8; 32], 2, &mut pk, &mut sk));
+    r.unwrap();
+    assert_eq!(n, 0, "round 3 key_pair_into");
+    let (r, n) = allocations(|| sign::sign_into(&sk, b"message", 2, &mut sig));
+    r.unwrap();
+    assert_eq!(n, 0, "round 3 sign_into");
+    let (ok, n) = allocations(|| sign::verify(&sig, &pk, b"message"));
+    assert!(ok);
+    assert_eq!(n, 0, "round 3 verify");
+}
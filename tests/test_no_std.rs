@@ -0,0 +1,173 @@
+// the functions writing into caller buffers, as a firmware without a heap would call them
+// the file is no_std itself and runs with cargo test --no-default-features
+#![no_std]
+
+// the harness runs on std anyway, the stack test uses its threads
+extern crate std;
+
+use dilithium_rust::error::Error;
+use dilithium_rust::mldsa;
+use dilithium_rust::params::{get_sizes, MlDsa44, MlDsa65, MlDsa87, Mode, ParameterSet};
+use dilithium_rust::sign;
+
+const MSG: &[u8] = b"firmware image";
+
+// the stack in KB that key_pair_into, sign_into and verify may use at a level, including the start of a thread
+// optimized builds need about 42/52/64, 46/54/64 and 34/39/45 KB at levels 2, 3 and 5 on x86_64
+#[cfg(not(debug_assertions))]
+const STACK_KB: [(u8, usize, usize, usize); 3] = [(2, 48, 52, 40), (3, 60, 64, 44), (5, 76, 76, 52)];
+// unoptimized builds keep every temporary in a slot of its own and need about 1.7 times as much
+#[cfg(debug_assertions)]
+const STACK_KB: [(u8, usize, usize, usize); 3] = [(2, 80, 84, 68), (3, 100, 100, 80), (5, 124, 124, 96)];
+
+// runs f on a thread with a stack of kb KB, using more aborts the process with
+// "thread 'verify at level 2' has overflowed its stack"
+fn with_stack<R: Send>(op: &str, level: u8, kb: usize, f: impl FnOnce() -> R + Send) -> R {
+    std::thread::scope(|s| {
+        std::thread::Builder::new()
+            .name(std::format!("{} at level {}", op, level))
+            .stack_size(kb * 1024)
+            .spawn_scoped(s, f)
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
+
+// one call of test_stack_budget, the keys and the signature are made on the stack of the test thread
+fn stack_case(op: &str, level: u8) {
+    let (_, key_pair_kb, sign_kb, verify_kb) = STACK_KB.into_iter().find(|budget| budget.0 == level).unwrap();
+    let (pkbytes, skbytes, sigbytes) = get_sizes(level, Mode::MlDsa);
+    let mut pk = [0u8; MlDsa87::PK_BYTES];
+    let mut sk = [0u8; MlDsa87::SK_BYTES];
+    let mut sig = [0u8; MlDsa87::SIG_BYTES];
+    let (pk, sk, sig) = (&mut pk[..pkbytes], &mut sk[..skbytes], &mut sig[..sigbytes]);
+    mldsa::key_pair_into(&[7u8; 32], level, pk, sk).unwrap();
+    mldsa::sign_into(sk, MSG, b"ctx", &[0u8; 32], level, sig).unwrap();
+    match op {
+        "key_pair_into" => {
+            with_stack(op, level, key_pair_kb, || mldsa::key_pair_into(&[7u8; 32], level, pk, sk)).unwrap()
+        }
+        "sign_into" => {
+            with_stack(op, level, sign_kb, || mldsa::sign_into(sk, MSG, b"ctx", &[0u8; 32], level, sig)).unwrap()
+        }
+        _ => assert!(with_stack(op, level, verify_kb, || mldsa::verify(sig, pk, MSG, b"ctx"))),
+    }
+}
+
+#[test]
+pub fn test_mldsa_into() {
+    let mut pk = [0u8; MlDsa44::PK_BYTES];
+    let mut sk = [0u8; MlDsa44::SK_BYTES];
+    let mut sig = [0u8; MlDsa44::SIG_BYTES];
+    mldsa::key_pair_into(&[1u8; 32], 2, &mut pk, &mut sk).unwrap();
+    mldsa::sign_into(&sk, MSG, b"ctx", &[0u8; 32], 2, &mut sig).unwrap();
+    assert!(mldsa::verify(&sig, &pk, MSG, b"ctx"));
+    assert!(!mldsa::verify(&sig, &pk, MSG, b""));
+
+    // the same rnd gives the same signature, a different one another valid signature
+    let mut sig2 = [0u8; MlDsa44::SIG_BYTES];
+    mldsa::sign_into(&sk, MSG, b"ctx", &[0u8; 32], 2, &mut sig2).unwrap();
+    assert_eq!(sig, sig2);
+    mldsa::sign_into(&sk, MSG, b"ctx", &[9u8; 32], 2, &mut sig2).unwrap();
+    assert_ne!(sig, sig2);
+    assert!(mldsa::verify(&sig2, &pk, MSG, b"ctx"));
+
+    let mut pk = [0u8; MlDsa65::PK_BYTES];
+    let mut sk = [0u8; MlDsa65::SK_BYTES];
+    let mut sig = [0u8; MlDsa65::SIG_BYTES];
+    mldsa::key_pair_into(&[2u8; 32], 3, &mut pk, &mut sk).unwrap();
+    mldsa::sign_into(&sk, MSG, &[], &[0u8; 32], 3, &mut sig).unwrap();
+    assert!(mldsa::verify(&sig, &pk, MSG, &[]));
+
+    let mut pk = [0u8; MlDsa87::PK_BYTES];
+    let mut sk = [0u8; MlDsa87::SK_BYTES];
+    let mut sig = [0u8; MlDsa87::SIG_BYTES];
+    mldsa::key_pair_into(&[3u8; 32], 5, &mut pk, &mut sk).unwrap();
+    mldsa::sign_into(&sk, MSG, &[], &[0u8; 32], 5, &mut sig).unwrap();
+    assert!(mldsa::verify(&sig, &pk, MSG, &[]));
+    sig[0] ^= 1;
+    assert!(!mldsa::verify(&sig, &pk, MSG, &[]));
+}
+
+#[test]
+pub fn test_dilithium_into() {
+    // round 3 keys have a 32 bytes tr
+    let mut pk = [0u8; MlDsa44::PK_BYTES];
+    let mut sk = [0u8; MlDsa44::SK_BYTES - 32];
+    let mut sig = [0u8; MlDsa44::SIG_BYTES];
+    sign::key_pair_into(&[4u8; 32], 2, &mut pk, &mut sk).unwrap();
+    sign::sign_into(&sk, MSG, 2, &mut sig).unwrap();
+    assert!(sign::verify(&sig, &pk, MSG));
+    assert!(!sign::verify(&sig, &pk, b"another image"));
+}
+
+#[test]
+pub fn test_into_errors() {
+    let mut pk = [0u8; MlDsa44::PK_BYTES];
+    let mut sk = [0u8; MlDsa44::SK_BYTES];
+    let mut sig = [0u8; MlDsa44::SIG_BYTES];
+    assert_eq!(mldsa::key_pair_into(&[0u8; 32], 4, &mut pk, &mut sk), Err(Error::UnsupportedLevel(4)));
+    assert_eq!(
+        mldsa::key_pair_into(&[0u8; 32], 3, &mut pk, &mut sk),
+        Err(Error::InvalidLength(MlDsa44::PK_BYTES))
+    );
+    assert_eq!(
+        mldsa::key_pair_into(&[0u8; 32], 2, &mut pk[1..], &mut sk),
+        Err(Error::InvalidLength(MlDsa44::PK_BYTES - 1))
+    );
+    mldsa::key_pair_into(&[0u8; 32], 2, &mut pk, &mut sk).unwrap();
+
+    assert_eq!(
+        mldsa::sign_into(&sk, MSG, &[0u8; 256], &[0u8; 32], 2, &mut sig),
+        Err(Error::ContextTooLong(256))
+    );
+    assert_eq!(
+        mldsa::sign_into(&sk, MSG, &[], &[0u8; 32], 2, &mut sig[..100]),
+        Err(Error::InvalidLength(100))
+    );
+    assert_eq!(
+        mldsa::sign_into(&sk[..MlDsa44::SK_BYTES - 32], MSG, &[], &[0u8; 32], 2, &mut sig),
+        Err(Error::InvalidLength(MlDsa44::SK_BYTES - 32))
+    );
+    assert_eq!(sign::sign_into(&sk, MSG, 2, &mut sig), Err(Error::InvalidLength(MlDsa44::SK_BYTES)));
+}
+
+// A is sampled as it is multiplied and the secret polynomials are unpacked one at a time,
+// so no call needs room for the whole matrix
+// every call runs in a fresh process of this binary, in which it runs alone as STACK_CASE says,
+// a thread would otherwise get the larger stack of an earlier thread from the cache of the C library
+#[test]
+pub fn test_stack_budget() {
+    if let Ok(case) = std::env::var("STACK_CASE") {
+        let (op, level) = case.split_once(' ').unwrap();
+        return stack_case(op, level.parse().unwrap());
+    }
+    for (level, ..) in STACK_KB {
+        for op in ["key_pair_into", "sign_into", "verify"] {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["test_stack_budget", "--exact", "--test-threads=1"])
+                .env("STACK_CASE", std::format!("{} {}", op, level))
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success(), "{} at level {} needs more stack than its budget", op, level);
+        }
+    }
+}
+
+// the functions returning Vec wrap the ones writing into buffers and give the same bytes
+#[cfg(feature = "alloc")]
+#[test]
+pub fn test_into_matches_vec() {
+    let (pk, sk) = mldsa::key_pair(&[5u8; 32], 3).unwrap();
+    let mut pk2 = [0u8; MlDsa65::PK_BYTES];
+    let mut sk2 = [0u8; MlDsa65::SK_BYTES];
+    mldsa::key_pair_into(&[5u8; 32], 3, &mut pk2, &mut sk2).unwrap();
+    assert_eq!(pk[..], pk2[..]);
+    assert_eq!(sk[..], sk2[..]);
+
+    let mut sig = [0u8; MlDsa65::SIG_BYTES];
+    mldsa::sign_into(&sk, MSG, b"ctx", &[6u8; 32], 3, &mut sig).unwrap();
+    assert_eq!(mldsa::sign_with_rnd(&sk, MSG, b"ctx", &[6u8; 32], 3).unwrap()[..], sig[..]);
+}
@@ -1,8 +1,12 @@
 mod cpucycle;
 
-use dilithium_rust::sign::{key_pair, sign, verify};
+use dilithium_rust::mldsa::{generate, OnlineSigner};
+use dilithium_rust::params::{MlDsa44, MlDsa65, MlDsa87, ParameterSet};
+use dilithium_rust::poly::{Poly, SparseTernary};
+use dilithium_rust::sign::{key_pair, sign, sign_into, verify};
 use crate::cpucycle::{cpucycles_overhead, cpucycles};
-use rand::RngCore;
+use rand::{Rng, RngCore};
+use std::hint::black_box;
 
 const NTEST:u64 = 1000;
 const LEVEL:u8 = 2;
@@ -12,21 +16,16 @@ pub fn test_key_gen_speed() {
     let mut rng = rand::thread_rng();
     let mut seed = [0u8; 32];
     rng.fill_bytes(&mut seed);
-    let mut t0 = 0u64;
-    let mut t1 = 0u64;
-    let mut cycles = 0u64;
-    let mut overhead = 0u64;
     let mut min_cycles = u64::MAX;
     let mut max_cycles = 0u64;
     let mut total_cycles = 0u64;
-    let mut avg_cycles = 0u64;
     let mut i = 0u32;
-    overhead = cpucycles_overhead();
+    let overhead = cpucycles_overhead();
     while i < NTEST as u32 {
-        t0 = cpucycles();
-        let (_pk, _sk) = key_pair(&seed, LEVEL);
-        t1 = cpucycles();
-        cycles = t1 - t0 - overhead;
+        let t0 = cpucycles();
+        let (_pk, _sk) = key_pair(&seed, LEVEL).unwrap();
+        let t1 = cpucycles();
+        let cycles = t1 - t0 - overhead;
         if cycles < min_cycles {
             min_cycles = cycles;
         }
@@ -36,7 +35,7 @@ pub fn test_key_gen_speed() {
         total_cycles += cycles;
         i += 1;
     }
-    avg_cycles = total_cycles / NTEST;
+    let avg_cycles = total_cycles / NTEST;
     println!("key_gen cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
 }
 
@@ -45,24 +44,19 @@ pub fn test_sign_speed() {
     let mut rng = rand::thread_rng();
     let mut seed = [0u8; 32];
     rng.fill_bytes(&mut seed);
-    let mut t0 = 0u64;
-    let mut t1 = 0u64;
-    let mut cycles = 0u64;
-    let mut overhead = 0u64;
     let mut min_cycles = u64::MAX;
     let mut max_cycles = 0u64;
     let mut total_cycles = 0u64;
-    let mut avg_cycles = 0u64;
     let mut i = 0u32;
-    overhead = cpucycles_overhead();
-    let (pk, sk) = key_pair(&seed, 2);
+    let overhead = cpucycles_overhead();
+    let (_pk, sk) = key_pair(&seed, LEVEL).unwrap();
     while i < NTEST as u32 {
         let mut msg = [0u8; 32];
         rng.fill_bytes(&mut msg);
-        t0 = cpucycles();
-        let sig = sign(&sk, &msg.to_vec(), LEVEL);
-        t1 = cpucycles();
-        cycles = t1 - t0 - overhead;
+        let t0 = cpucycles();
+        let _sig = sign(&sk, &msg, LEVEL).unwrap();
+        let t1 = cpucycles();
+        let cycles = t1 - t0 - overhead;
         if cycles < min_cycles {
             min_cycles = cycles;
         }
@@ -72,34 +66,63 @@ pub fn test_sign_speed() {
         total_cycles += cycles;
         i += 1;
     }
-    avg_cycles = total_cycles / NTEST;
+    let avg_cycles = total_cycles / NTEST;
     println!("sign cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
 }
 
+// sign_into reads the packed key as signing without alloc does, sampling A again for every attempt
+#[test]
+pub fn test_sign_into_speed() {
+    let mut rng = rand::thread_rng();
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    let mut min_cycles = u64::MAX;
+    let mut max_cycles = 0u64;
+    let mut total_cycles = 0u64;
+    let mut i = 0u32;
+    let overhead = cpucycles_overhead();
+    let (_pk, sk) = key_pair(&seed, LEVEL).unwrap();
+    let mut sig = [0u8; MlDsa44::SIG_BYTES];
+    while i < NTEST as u32 {
+        let mut msg = [0u8; 32];
+        rng.fill_bytes(&mut msg);
+        let t0 = cpucycles();
+        sign_into(&sk, &msg, LEVEL, &mut sig).unwrap();
+        let t1 = cpucycles();
+        let cycles = t1 - t0 - overhead;
+        if cycles < min_cycles {
+            min_cycles = cycles;
+        }
+        if cycles > max_cycles {
+            max_cycles = cycles;
+        }
+        total_cycles += cycles;
+        i += 1;
+    }
+    let avg_cycles = total_cycles / NTEST;
+    println!("sign_into cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
+}
+
 #[test]
 pub fn test_verify_speed() {
     let mut rng = rand::thread_rng();
     let mut seed = [0u8; 32];
     rng.fill_bytes(&mut seed);
-    let mut t0 = 0u64;
-    let mut t1 = 0u64;
-    let mut cycles = 0u64;
-    let mut overhead = 0u64;
     let mut min_cycles = u64::MAX;
     let mut max_cycles = 0u64;
     let mut total_cycles = 0u64;
-    let mut avg_cycles = 0u64;
     let mut i = 0u32;
-    overhead = cpucycles_overhead();
-    let (pk, sk) = key_pair(&seed, 2);
+    let overhead = cpucycles_overhead();
+    let (pk, sk) = key_pair(&seed, LEVEL).unwrap();
     while i < NTEST as u32 {
         let mut msg = [0u8; 32];
         rng.fill_bytes(&mut msg);
-        let sig = sign(&sk, &msg.to_vec(), LEVEL);
-        t0 = cpucycles();
-        let res = verify(&sig, &pk, &msg.to_vec());
-        t1 = cpucycles();
-        cycles = t1 - t0 - overhead;
+        let sig = sign(&sk, &msg, LEVEL).unwrap();
+        let t0 = cpucycles();
+        let res = verify(&sig, &pk, &msg);
+        let t1 = cpucycles();
+        assert!(res);
+        let cycles = t1 - t0 - overhead;
         if cycles < min_cycles {
             min_cycles = cycles;
         }
@@ -109,6 +132,166 @@ pub fn test_verify_speed() {
         total_cycles += cycles;
         i += 1;
     }
-    avg_cycles = total_cycles / NTEST;
+    let avg_cycles = total_cycles / NTEST;
     println!("verify cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_prepared_sign_speed() {
+    let mut rng = rand::thread_rng();
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    let mut min_cycles = u64::MAX;
+    let mut max_cycles = 0u64;
+    let mut total_cycles = 0u64;
+    let mut i = 0u32;
+    let overhead = cpucycles_overhead();
+    let (sk, _vk) = generate(&seed, LEVEL).unwrap();
+    let prepared = sk.prepare();
+    while i < NTEST as u32 {
+        let mut msg = [0u8; 32];
+        rng.fill_bytes(&mut msg);
+        let t0 = cpucycles();
+        let _sig = prepared.sign(&msg, b"");
+        let t1 = cpucycles();
+        let cycles = t1 - t0 - overhead;
+        if cycles < min_cycles {
+            min_cycles = cycles;
+        }
+        if cycles > max_cycles {
+            max_cycles = cycles;
+        }
+        total_cycles += cycles;
+        i += 1;
+    }
+    let avg_cycles = total_cycles / NTEST;
+    println!("prepared sign cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
+}
+
+#[test]
+pub fn test_online_sign_speed() {
+    let mut rng = rand::thread_rng();
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    let mut min_cycles = u64::MAX;
+    let mut max_cycles = 0u64;
+    let mut total_cycles = 0u64;
+    let mut i = 0u32;
+    let overhead = cpucycles_overhead();
+    let (sk, _vk) = generate(&seed, LEVEL).unwrap();
+    let mut signer = OnlineSigner::new(sk.prepare());
+    while i < NTEST as u32 {
+        let mut msg = [0u8; 32];
+        rng.fill_bytes(&mut msg);
+        // the pool is refilled outside of the measurement, 16 commitments are almost never exhausted
+        signer.precompute(16 - signer.available(), &mut rng);
+        let t0 = cpucycles();
+        let _sig = signer.sign(&msg, b"", &mut rng);
+        let t1 = cpucycles();
+        let cycles = t1 - t0 - overhead;
+        if cycles < min_cycles {
+            min_cycles = cycles;
+        }
+        if cycles > max_cycles {
+            max_cycles = cycles;
+        }
+        total_cycles += cycles;
+        i += 1;
+    }
+    let avg_cycles = total_cycles / NTEST;
+    println!("online sign cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
+}
+
+#[test]
+pub fn test_prepared_verify_speed() {
+    let mut rng = rand::thread_rng();
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    let mut min_cycles = u64::MAX;
+    let mut max_cycles = 0u64;
+    let mut total_cycles = 0u64;
+    let mut i = 0u32;
+    let overhead = cpucycles_overhead();
+    let (sk, vk) = generate(&seed, LEVEL).unwrap();
+    let prepared = vk.prepare();
+    while i < NTEST as u32 {
+        let mut msg = [0u8; 32];
+        rng.fill_bytes(&mut msg);
+        let sig = sk.sign(&msg, b"").unwrap();
+        let t0 = cpucycles();
+        let res = prepared.verify(&msg, b"", &sig);
+        let t1 = cpucycles();
+        assert!(res.is_ok());
+        let cycles = t1 - t0 - overhead;
+        if cycles < min_cycles {
+            min_cycles = cycles;
+        }
+        if cycles > max_cycles {
+            max_cycles = cycles;
+        }
+        total_cycles += cycles;
+        i += 1;
+    }
+    let avg_cycles = total_cycles / NTEST;
+    println!("prepared verify cycles: min: {}, max: {}, avg: {}", min_cycles, max_cycles, avg_cycles);
+}
+// the cycles of c * s1, c * s2 and c * t0 in one attempt of the rejection loop through the NTT, with s1, s2 and t0
+// in NTT form, and with the sparse multiplication, see ParameterSet::SPARSE_CHALLENGE
+// both run on the same challenges one after the other, so that they see the same state of the caches and the clock
+fn challenge_mul_cycles<P: ParameterSet>(rng: &mut impl Rng) -> [(u64, u64); 2] {
+    let n = P::L + 2 * P::K;
+    let mut s: Vec<Poly> = (0..n).map(|_| Poly::new()).collect();
+    for (i, p) in s.iter_mut().enumerate() {
+        // s1 and s2 in [-eta, eta], t0 in (-2^12, 2^12]
+        let bound = if i < P::L + P::K { P::ETA } else { 1 << 12 };
+        p.coeffs.iter_mut().for_each(|c| *c = rng.gen_range(-bound + 1..=bound));
+    }
+    let s_hat: Vec<Poly> = s.iter().map(Poly::ntt).collect();
+    let mut out = Poly::new();
+    let mut min_cycles = [u64::MAX; 2];
+    let mut total_cycles = [0u64; 2];
+    let overhead = cpucycles_overhead();
+    for _ in 0..NTEST {
+        let mut c = Poly::new();
+        for _ in 0..P::TAU {
+            c.coeffs[rng.gen_range(0..256)] = if rng.gen() { 1 } else { -1 };
+        }
+        let t0 = cpucycles();
+        let mut c_hat = c.copy();
+        c_hat.ntt_in_place();
+        for p in s_hat.iter() {
+            out.coeffs = [0; 256];
+            out.point_wise_mul_acc(&c_hat, p);
+            out.intt_in_place();
+            black_box(&out);
+        }
+        let t1 = cpucycles();
+        let c = SparseTernary::new(&c);
+        for p in s.iter() {
+            out.coeffs = [0; 256];
+            out.sparse_mul_acc(&c, p);
+            black_box(&out);
+        }
+        let t2 = cpucycles();
+        for (i, cycles) in [t1 - t0 - overhead, t2 - t1 - overhead].into_iter().enumerate() {
+            min_cycles[i] = min_cycles[i].min(cycles);
+            total_cycles[i] += cycles;
+        }
+    }
+    [0, 1].map(|i| (min_cycles[i], total_cycles[i] / NTEST))
+}
+
+#[test]
+pub fn test_challenge_mul_speed() {
+    let mut rng = rand::thread_rng();
+    for (level, [ntt, sparse]) in [
+        (2, challenge_mul_cycles::<MlDsa44>(&mut rng)),
+        (3, challenge_mul_cycles::<MlDsa65>(&mut rng)),
+        (5, challenge_mul_cycles::<MlDsa87>(&mut rng)),
+    ] {
+        println!(
+            "level {} challenge mul cycles: ntt min: {}, avg: {}, sparse min: {}, avg: {}",
+            level, ntt.0, ntt.1, sparse.0, sparse.1
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// every buffer freed while a signing key is used is scanned for pieces of the secret key,
+// the global allocator applies to the whole test binary, so this file holds a single test
+use dilithium_rust::mldsa::{generate, key_pair, OnlineSigner, SigningKey};
+use dilithium_rust::poly::Poly;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::Shake256;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+// K, packed s1, packed t0, s1 and NTT(s1) as i32, y of the first and of the last precomputed commitment
+const PATTERNS: usize = 7;
+const PATTERN_LEN: usize = 32;
+
+struct ScanningAllocator;
+
+static ARMED: AtomicBool = AtomicBool::new(false);
+static SCANNED: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU8 = AtomicU8::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZEROS: [AtomicU8; PATTERN_LEN] = [ZERO; PATTERN_LEN];
+static SECRETS: [[AtomicU8; PATTERN_LEN]; PATTERNS] = [ZEROS; PATTERNS];
+#[allow(clippy::declare_interior_mutable_const)]
+const NONE_FOUND: AtomicUsize = AtomicUsize::new(0);
+static FOUND: [AtomicUsize; PATTERNS] = [NONE_FOUND; PATTERNS];
+
+unsafe impl GlobalAlloc for ScanningAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if ARMED.load(Ordering::SeqCst) {
+            let freed = std::slice::from_raw_parts(ptr, layout.size());
+            for (secret, found) in SECRETS.iter().zip(&FOUND) {
+                let mut pattern = [0u8; PATTERN_LEN];
+                for (b, s) in pattern.iter_mut().zip(secret) {
+                    *b = s.load(Ordering::SeqCst);
+                }
+                if freed.windows(PATTERN_LEN).any(|w| w == pattern) {
+                    found.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+            SCANNED.fetch_add(1, Ordering::SeqCst);
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: ScanningAllocator = ScanningAllocator;
+
+fn set_pattern(i: usize, bytes: &[u8]) {
+    assert!(bytes.iter().any(|&b| b != 0), "pattern {} would match a wiped buffer", i);
+    for (s, b) in SECRETS[i].iter().zip(bytes) {
+        s.store(*b, Ordering::SeqCst);
+    }
+}
+
+// the bytes of the first coefficients of p as they lie in memory
+fn coeff_bytes(p: &Poly) -> Vec<u8> {
+    p.coeffs[..PATTERN_LEN / 4].iter().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+// the coefficient i of a polynomial packed with bits bits per coefficient, little endian
+fn unpack_bits(bytes: &[u8], bits: usize, i: usize) -> i32 {
+    (0..bits).map(|b| ((bytes[(i * bits + b) / 8] >> ((i * bits + b) % 8)) as i32 & 1) << b).sum()
+}
+
+// the first polynomial of s1, packed as eta - s1 with 3 bits for eta 2 and 4 bits for eta 4
+fn first_s1(sk: &[u8], eta: i32) -> Poly {
+    let mut p = Poly::new();
+    for (i, c) in p.coeffs.iter_mut().enumerate() {
+        *c = eta - unpack_bits(&sk[128..], if eta == 2 { 3 } else { 4 }, i);
+    }
+    p
+}
+
+// the first polynomial of y of a commitment precomputed with rnd, y = ExpandMask(H(K || rnd), 0)
+fn first_y(key: &[u8], rnd: &[u8; 32], gamma1: i32) -> Poly {
+    let mut rhoprime = [0u8; 64];
+    let mut h = Shake256::default();
+    h.update(key);
+    h.update(rnd);
+    h.finalize_xof().read(&mut rhoprime);
+    // y is packed as gamma1 - y with 18 bits for gamma1 2^17 and 20 bits for 2^19
+    let bits = if gamma1 == 1 << 17 { 18 } else { 20 };
+    let mut buf = vec![0u8; 32 * bits];
+    let mut h = Shake256::default();
+    h.update(&rhoprime);
+    h.update(&[0, 0]);
+    h.finalize_xof().read(&mut buf);
+    let mut y = Poly::new();
+    for (i, c) in y.coeffs.iter_mut().enumerate() {
+        *c = gamma1 - unpack_bits(&buf, bits, i);
+    }
+    y
+}
+
+fn found() -> Vec<usize> {
+    FOUND.iter().map(|f| f.swap(0, Ordering::SeqCst)).collect()
+}
+
+#[test]
+pub fn test_freed_buffers_hold_no_secrets() {
+    let seed = [0x5au8; 32];
+    let msg = [0x17u8; 100];
+    let mut rng = rand::thread_rng();
+    // k, l, eta, gamma1 and the bytes of a packed polynomial of s1 or s2
+    for (security_level, k, l, eta, gamma1, polyeta) in
+        [(2, 4, 4, 2, 1 << 17, 96), (3, 6, 5, 4, 1 << 19, 128), (5, 8, 7, 2, 1 << 19, 96)]
+    {
+        // K, the start of the packed s1 and the start of the packed t0
+        let (_, sk) = key_pair(&seed, security_level).unwrap();
+        let offsets = [32, 128, 128 + polyeta * (k + l)];
+        for (i, offset) in offsets.into_iter().enumerate() {
+            set_pattern(i, &sk[offset..offset + PATTERN_LEN]);
+        }
+        // s1 as the prepared key stores it, in normal form at levels 2 and 3 and in NTT form at level 5
+        let s1 = first_s1(&sk, eta);
+        let mut s1_hat = s1.copy();
+        s1_hat.ntt_in_place();
+        set_pattern(3, &coeff_bytes(&s1));
+        set_pattern(4, &coeff_bytes(&s1_hat));
+        // the pool draws 32 bytes of rnd per commitment, 23 commitments are precomputed below
+        let pool_seed = security_level as u64;
+        let mut rnds = StdRng::seed_from_u64(pool_seed);
+        let rnds: Vec<[u8; 32]> = (0..23)
+            .map(|_| {
+                let mut rnd = [0u8; 32];
+                rnds.fill_bytes(&mut rnd);
+                rnd
+            })
+            .collect();
+        let y_first = first_y(&sk[32..64], &rnds[0], gamma1);
+        let y_last = first_y(&sk[32..64], &rnds[22], gamma1);
+        set_pattern(5, &coeff_bytes(&y_first));
+        set_pattern(6, &coeff_bytes(&y_last));
+        drop(sk);
+        found();
+
+        ARMED.store(true, Ordering::SeqCst);
+        let (_pk, sk) = key_pair(&seed, security_level).unwrap();
+        let (signing_key, verifying_key) = generate(&seed, security_level).unwrap();
+        let sig = signing_key.sign(&msg, b"ctx").unwrap();
+        assert!(verifying_key.verify(&msg, b"ctx", &sig).is_ok());
+        signing_key.sign_randomized(&msg, b"ctx", &mut rng).unwrap();
+        let loaded = SigningKey::from_bytes(&signing_key.to_bytes()).unwrap();
+        assert_eq!(loaded.verifying_key().unwrap(), verifying_key);
+        let prepared = loaded.prepare();
+        prepared.sign(&msg, b"ctx").unwrap();
+        // growing the pool from 3 to 23 commitments moves y_first, signing pops y_last first
+        let mut signer = OnlineSigner::new(prepared.clone());
+        let mut pool_rng = StdRng::seed_from_u64(pool_seed);
+        signer.precompute(3, &mut pool_rng);
+        signer.precompute(20, &mut pool_rng);
+        for _ in 0..4 {
+            signer.sign(&msg, b"ctx", &mut rng).unwrap();
+        }
+        drop((signing_key, loaded, prepared, signer));
+        ARMED.store(false, Ordering::SeqCst);
+
+        assert!(SCANNED.load(Ordering::SeqCst) > 0);
+        assert_eq!(found(), [0; PATTERNS], "a freed buffer held a secret of level {}", security_level);
+
+        // negative control: copies that are not wiped are found
+        ARMED.store(true, Ordering::SeqCst);
+        drop(black_box(sk.to_vec()));
+        for p in [&s1, &s1_hat, &y_first, &y_last] {
+            drop(black_box(Box::new(p.coeffs)));
+        }
+        ARMED.store(false, Ordering::SeqCst);
+        assert!(found().iter().all(|&n| n > 0), "the scan missed an unwiped buffer at level {}", security_level);
+    }
+}